@@ -24,7 +24,7 @@ impl<R: Read> Commands<R> {
 		);
 		
 		let data = match data {
-			Ok(data) if data.is_empty() => return None,
+			Ok([]) => return None,
 			Err(err) => return Some(Err(err.into())),
 			Ok(data) => data,
 		};
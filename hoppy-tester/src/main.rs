@@ -120,12 +120,8 @@ fn send_requests(mut writer: impl Write, rx: Receiver<String>) {
 		writer.write_all(b"\r\n")
 			.expect("couldn't write to port");
 		
-		loop {
-			let Ok(reply_text) = rx.recv_timeout(Duration::from_secs(2)) else {
-				// timeout
-				break;
-			};
-			
+		// stop waiting for replies once the timeout runs out
+		while let Ok(reply_text) = rx.recv_timeout(Duration::from_secs(2)) {
 			println!("< {reply_text}");
 		}
 	}
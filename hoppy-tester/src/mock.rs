@@ -109,7 +109,7 @@ fn handle_command(mut port: impl Read + Write, state: &mut State, command: Vec<u
 	let reply = if command == b"AT" {
 		b"AT,OK\r\n".to_vec()
 	} else if command.starts_with(b"AT+SEND=") {
-		handle_send(&mut port, &state, &command[8..])?.to_owned()
+		handle_send(&mut port, state, &command[8..])?.to_owned()
 	} else if command.starts_with(b"AT+ADDR=") {
 		set_address(state, &command[8..])?.to_owned()
 	} else if command.starts_with(b"AT+ADDR?") {
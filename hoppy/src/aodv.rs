@@ -1,399 +1,339 @@
+mod delivery;
+mod event_loop;
+mod events;
+mod flooding;
+mod hello_schedule;
+mod link_quality;
+mod message_queue;
+mod metric;
+mod neighbor_filter;
+mod neighbor_table;
+mod node_info;
 mod packets;
+mod parameters;
+mod protocol;
+mod rate_limit;
+mod routing_snapshot;
 mod routing_table;
+mod stats;
 
-use std::{io, thread, sync::{Mutex, Arc, RwLock, MutexGuard, RwLockWriteGuard, RwLockReadGuard, atomic::{AtomicU16, Ordering}}, collections::{BTreeSet, BTreeMap}, time::{Duration, Instant}};
+#[cfg(feature = "tokio")]
+mod async_controller;
 
-use crate::at_module::{ATModule, at_address::ATAddress, ATModuleBuilder};
+use std::{io::{self, ErrorKind}, thread::{self, JoinHandle}, sync::{mpsc::{self, Sender}, atomic::{AtomicU16, Ordering}}, collections::{BTreeSet, BTreeMap}, time::{Duration, Instant}};
 
-use packets::*;
-use routing_table::RoutingTable;
+use tracing::debug;
 
-use self::routing_table::Route;
+use crate::at_module::{at_address::ATAddress, ATCounters, ATModuleBuilder};
 
-pub struct AODVController<C: Fn(ATAddress, &[u8]) + Send + Sync> {
-	seen_requests: Mutex<BTreeSet<(ATAddress, u16)>>, // unfortunate mutex
-	routing_table: RwLock<RoutingTable>,
-	at_module: Mutex<ATModule>,
-	outbound_messages: Mutex<BTreeMap<ATAddress, Vec<Box<[u8]>>>>,
-	address: ATAddress,
-	current_route_request_id: AtomicU16,
-	current_sequence_number: AtomicU16,
-	hello_timeout: Duration,
-	data_callback: C,
+use event_loop::{Command, EventLoop, write_transmissions};
+use message_queue::message_queue;
+
+pub use delivery::{DeliveryHandle, DeliveryStatus, DeliveryFailure};
+pub use events::{AODVEvent, DropReason, EventReceiver};
+pub use flooding::{FloodingConfig, BroadcastSuppression};
+pub use hello_schedule::HelloIntervalConfig;
+pub use link_quality::LinkQuality;
+pub use message_queue::{ReceivedMessage, MessageReceiver, Overflow};
+pub use metric::{RouteMetric, HopCount, Etx, SignalStrength, LINK_COST_UNIT};
+pub use neighbor_filter::NeighborFilter;
+pub use neighbor_table::{NeighborStats, LinkState};
+pub use node_info::{NodeInfo, Capabilities, MAX_NAME_LENGTH};
+pub use packets::PacketType;
+pub use parameters::{AODVParameters, ParameterError};
+pub use protocol::{AODVProtocol, Action, Timer};
+pub use rate_limit::{RouteRequestLimits, RateLimit};
+pub use routing_snapshot::{RoutingSnapshot, RoutingEntry, RouteInfo};
+pub use routing_table::StaticRoute;
+pub use stats::{AODVStats, ControllerStats, QueueDepths};
+
+#[cfg(feature = "tokio")]
+pub use async_controller::AsyncAODVController;
+
+/// Retransmissions wait at most 2^MAX_BACKOFF_EXPONENT times as long as the first transmission
+const MAX_BACKOFF_EXPONENT: u32 = 6;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LinkAckConfig {
+	/// How long to wait for the first acknowledgement, doubled on every retransmission
+	pub timeout: Duration,
+	/// Number of retransmissions before the link is considered broken
+	pub max_retries: u8,
+}
+
+impl LinkAckConfig {
+	/// Timeout doubled for every retransmission, up to `MAX_BACKOFF_EXPONENT` times
+	fn backoff_timeout(&self, retries: u8) -> Duration {
+		self.timeout.saturating_mul(1 << (retries as u32).min(MAX_BACKOFF_EXPONENT))
+	}
+	
+	/// Time from the first transmission until the last retransmission times out
+	fn retransmission_window(&self) -> Duration {
+		(0..=self.max_retries)
+			.map(|retries| self.backoff_timeout(retries))
+			.fold(Duration::ZERO, Duration::saturating_add)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeliveryConfig {
+	/// How long to wait for an end-to-end acknowledgement before retransmitting
+	pub timeout: Duration,
+	/// Number of retransmissions before the delivery is considered failed
+	pub max_retries: u8,
+}
+
+impl DeliveryConfig {
+	/// Time from the first transmission until the last retransmission times out
+	fn retransmission_window(&self) -> Duration {
+		self.timeout * (self.max_retries as u32 + 1)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShutdownConfig {
+	/// How long to keep running while data still waits for a route or an acknowledgement, if at all
+	pub flush_timeout: Option<Duration>,
+	/// Whether to broadcast a RouteErrorPacket for this node, so neighbors drop their routes over it right away
+	pub goodbye: bool,
 }
 
-impl<'scope, C: Fn(ATAddress, &[u8]) + Send + Sync + 'scope> AODVController<C> {
-	pub fn start(
-		scope: &'scope thread::Scope<'scope, '_>,
+impl Default for ShutdownConfig {
+	fn default() -> Self {
+		Self {
+			flush_timeout: None,
+			goodbye: true,
+		}
+	}
+}
+
+/// Runs an `AODVProtocol` on an event loop thread, fed by the AT module and the methods of this handle
+///
+/// All methods only queue a command, so they never wait for the serial port.
+/// Dropping the controller shuts it down with the default `ShutdownConfig`.
+pub struct AODVController {
+	commands: Sender<Command>,
+	current_data_id: AtomicU16,
+	at_counters: ATCounters,
+	threads: Vec<JoinHandle<()>>,
+}
+
+impl AODVController {
+	/// Fails with `ErrorKind::InvalidInput` if the parameters are not valid, see `AODVParameters::validate`
+	pub fn start<C: FnMut(ATAddress, &[u8]) + Send + 'static>(
 		at_module_builder: ATModuleBuilder,
-		hello_interval: Duration,
-		hello_timeout: Duration,
+		parameters: AODVParameters,
+		metric: Box<dyn RouteMetric>,
+		node_info: NodeInfo,
 		data_callback: C
-	) -> Arc<Self> {
+	) -> Result<Self, io::Error> {
+		// checked before the AT module starts reading
+		parameters.validate()
+			.map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+		
 		let (at_module, at_message_receiver) = at_module_builder.build();
-		let address = at_module.address();
-		let routing_table = RoutingTable::new(address);
+		let at_counters = at_module.counters();
+		
+		let protocol = AODVProtocol::new(at_module.address(), parameters, metric, node_info, Instant::now())
+			.expect("parameters were validated");
 		
-		let controller = AODVController {
-			seen_requests: Default::default(),
-			at_module: Mutex::new(at_module),
-			routing_table: RwLock::new(routing_table),
-			outbound_messages: Default::default(),
-			address,
-			current_route_request_id: 0.into(),
-			current_sequence_number: 0.into(),
-			hello_timeout,
-			data_callback,
-		};
+		let (command_sender, command_receiver) = mpsc::channel();
+		let (transmission_sender, transmission_receiver) = mpsc::channel();
 		
-		let controller = Arc::new(controller);
-		let controller_receive = Arc::clone(&controller);
-		let controller_hello = Arc::clone(&controller);
+		let frame_sender = command_sender.clone();
 		
-		scope.spawn(move || {
+		let receive_thread = thread::spawn(move || {
 			for message in at_message_receiver {
-				println!("[INFO] Received message:\n\t{message}");
+				debug!(sender = %message.address, "Received message:\n\t{message}");
 				
-				let packet = match parse_packet(&message) {
-					Ok(packet) => packet,
-					Err(err) => {
-						eprintln!("[ERROR] Encountered invalid packet ({err}):\n\t{message}");
-						continue;
-					},
-				};
-				
-				if let Err(err) = controller_receive.handle_packet(&packet) {
-					eprintln!("[Error] Error occured trying to handle a packet ({err}):\n{packet:#?}");
+				if frame_sender.send(Command::Frame(message)).is_err() {
+					break;
 				}
 			}
 		});
 		
-		scope.spawn(move || {
-			loop {
-				let result = controller_hello.send_hello();
-				
-				if let Err(err) = result {
-					eprintln!("[ERROR] Could not send Hello packet ({err})");
-				}
-				
-				let result = controller_hello.check_neighbor_hello();
-				
-				if let Err(err) = result {
-					eprintln!("[ERROR] Could not send RouteErrorPacket ({err})")
-				}
-				
-				thread::sleep(hello_interval);
-			}
+		let writer_thread = thread::spawn(move || {
+			write_transmissions(at_module, transmission_receiver);
 		});
 		
-		controller
-	}
-	
-	fn at_module_write(&self) -> MutexGuard<ATModule> {
-		self.at_module.lock()
-			.expect("no threads should panic")
+		let event_loop_thread = thread::spawn(move || {
+			EventLoop::new(protocol, data_callback)
+				.run(command_receiver, transmission_sender);
+		});
+		
+		Ok(AODVController {
+			commands: command_sender,
+			// random, so a restarted node's messages aren't taken for duplicates of the ones it sent before
+			current_data_id: rand::random::<u16>().into(),
+			at_counters,
+			// joined in this order, the receive thread stops once the closed AT module stopped reading
+			threads: vec![event_loop_thread, writer_thread, receive_thread],
+		})
 	}
 	
-	fn routing_table_read(&self) -> RwLockReadGuard<RoutingTable> {
-		self.routing_table.read()
-			.expect("no threads should panic")
+	/// Stops all threads and closes the AT module, which takes up to the read timeout of the serial port
+	pub fn shutdown(mut self, config: ShutdownConfig) {
+		self.stop(config);
 	}
 	
-	fn routing_table_write(&self) -> RwLockWriteGuard<RoutingTable> {
-		self.routing_table.write()
-			.expect("no threads should panic")
+	fn stop(&mut self, config: ShutdownConfig) {
+		// the event loop might already be gone, the threads are joined either way
+		let _ = self.command(Command::Shutdown(config));
+		
+		for thread in self.threads.drain(..) {
+			thread.join()
+				.expect("no threads should panic");
+		}
 	}
 	
-	fn outbound_messages_write(&self) -> MutexGuard<BTreeMap<ATAddress, Vec<Box<[u8]>>>> {
-		self.outbound_messages.lock()
-			.expect("no threads should panic")
+	fn command(&self, command: Command) -> Result<(), io::Error> {
+		self.commands.send(command)
+			.map_err(|_| ErrorKind::BrokenPipe.into())
 	}
 	
-	pub fn send(&self, address: ATAddress, data: Box<[u8]>) -> Result<(), io::Error> {
-		let routing_table = self.routing_table_read();
-		let mut at_module = self.at_module_write();
-		
-		if let Some(route) = routing_table.get_route(address, None) {
-			let packet = DataPacket {
-				destination: address,
-				origin: self.address,
-				payload: data,
-			};
-			
-			at_module.send(route.next_hop, &packet.to_bytes())?;
-			
-			return Ok(());
-		}
+	/// Asks the event loop for a value, which is the default if the event loop stopped
+	fn query<T: Default + Send + 'static>(&self, query: impl FnOnce(&mut AODVProtocol, Instant) -> T + Send + 'static) -> T {
+		let (sender, receiver) = mpsc::channel();
 		
-		let packet = RouteRequestPacket {
-			id: self.current_route_request_id.fetch_add(1, Ordering::Relaxed),
-			hop_count: 0,
-			destination: address,
-			destination_sequence: routing_table.get_last_known_sequence(address),
-			origin: self.address,
-			origin_sequence: self.current_sequence_number.fetch_add(1, Ordering::Relaxed),
-		};
+		let command = Command::Query(Box::new(move |protocol, now| {
+			// the caller might have stopped waiting
+			let _ = sender.send(query(protocol, now));
+		}));
 		
-		at_module.broadcast(&packet.to_bytes())?;
-		
-		let mut outbound_messages = self.outbound_messages.lock()
-			.expect("no threads should panic");
-		
-		outbound_messages.entry(address)
-			.or_insert_with(Vec::new)
-			.push(data);
+		if self.command(command).is_err() {
+			return T::default();
+		}
 		
-		Ok(())
+		receiver.recv()
+			.unwrap_or_default()
 	}
 	
-	fn send_hello(&self) -> Result<(), io::Error> {
-		let mut at_module = self.at_module_write();
+	/// Queues data received from now on, in addition to passing it to the data callback
+	///
+	/// The event loop never waits for a full queue, `overflow` decides which message is dropped instead.
+	pub fn message_receiver(&self, capacity: usize, overflow: Overflow) -> Result<MessageReceiver, io::Error> {
+		let (sender, receiver) = message_queue(capacity, overflow);
 		
-		let packet = RouteReplyPacket {
-			hop_count: 0,
-			request_destination: self.address,
-			request_destination_sequence: self.current_sequence_number.fetch_add(1, Ordering::Relaxed),
-			request_origin: None,
-		};
+		self.command(Command::Subscribe(Box::new(sender)))?;
 		
-		at_module.broadcast(&packet.to_bytes())?;
-		
-		Ok(())
+		Ok(receiver)
 	}
 	
-	fn check_neighbor_hello(&self) -> Result<(), io::Error> {
-		let routing_table = self.routing_table_read();
-		
-		let current_time = Instant::now();
-		
-		let timed_out_routes: Vec<_> = routing_table.neighbors()
-			.filter(|neighbor| current_time - neighbor.last_seen > self.hello_timeout)
-			.flat_map(|neighbor| routing_table.routes_with_next_hop(neighbor.next_hop))
-			.collect();
+	/// Queues routing and neighbor events from now on, dropping events like `message_receiver` when the queue is full
+	pub fn event_receiver(&self, capacity: usize, overflow: Overflow) -> Result<EventReceiver, io::Error> {
+		let (sender, receiver) = message_queue(capacity, overflow);
 		
-		let mut timed_out_neighbors = Vec::new();
+		self.command(Command::SubscribeEvents(Box::new(sender)))?;
 		
-		for neighbor in routing_table.neighbors() {
-			if current_time - neighbor.last_seen > self.hello_timeout {
-				timed_out_neighbors.push(neighbor);
-			}
-		}
-		
-		// avoid taking write locks if no neighbor timed out
-		if timed_out_neighbors.is_empty() {
-			return Ok(());
-		}
-		
-		// release read lock
-		std::mem::drop(routing_table);
-		
-		let mut routing_table = self.routing_table_write();
-		let mut at_module = self.at_module_write();
-		
-		for (destination, route) in timed_out_routes {
-			routing_table.remove_route(destination, route.next_hop);
-			
-			let packet = RouteErrorPacket {
-				destination,
-			};
-			
-			at_module.broadcast(&packet.to_bytes())?;
-		}
-		
-		Ok(())
+		Ok(receiver)
 	}
 	
-	fn send_outbound_messages(&self, at_module: &mut ATModule, destination: ATAddress, route: Route) -> Result<(), io::Error> {
-		let mut outbound_messages = self.outbound_messages_write();
-		
-		let Some(messages) = outbound_messages.get_mut(&destination) else {
-			return Ok(());
-		};
-		
-		for message in messages.drain(..) {
-			let packet = DataPacket {
-				destination,
-				origin: self.address,
-				payload: message,
-			};
-			
-			at_module.send(route.next_hop, &packet.to_bytes())?;
-		}
-		
-		Ok(())
+	/// Link statistics of all nodes this node received frames from recently
+	pub fn neighbor_stats(&self) -> Vec<NeighborStats> {
+		self.query(|protocol, now| protocol.neighbor_stats(now))
 	}
 	
-	fn update_sequence_number(&self, new_sequence_number: u16) {
-		let current = &self.current_sequence_number;
-		let new = new_sequence_number;
-		let mut old = self.current_sequence_number.load(Ordering::Relaxed);
-		
-		loop {
-			let max = if sequence_number_newer(new, old) {
-				new
-			} else {
-				old
-			};
-			
-			match current.compare_exchange_weak(old, max, Ordering::Relaxed, Ordering::Relaxed) {
-				Ok(_) => break,
-				Err(i) => old = i,
-			}
-		}
+	/// Nodes that are not neighbors but can be reached over one, each with the neighbors that can reach it
+	pub fn two_hop_neighbors(&self) -> BTreeMap<ATAddress, BTreeSet<ATAddress>> {
+		self.query(|protocol, _| protocol.two_hop_neighbors())
 	}
 	
-	fn handle_packet(&self, packet: &AODVPacket) -> Result<(), io::Error> {
-		use AODVPacketBody::*;
-		
-		let sender = packet.sender;
-		
-		match &packet.body {
-			RouteRequest(packet) => self.handle_route_request(sender, packet)?,
-			RouteReply(packet) => self.handle_route_reply(sender, packet)?,
-			RouteError(packet) => self.handle_route_error(sender, packet)?,
-			Data(packet) => self.handle_data(packet)?,
-		}
-		
-		Ok(())
+	/// Neighbors whose route requests are dropped for exceeding the rate limit, with the time they are dropped until
+	pub fn throttled_neighbors(&self) -> Vec<(ATAddress, Instant)> {
+		self.query(|protocol, now| protocol.throttled_neighbors(now))
 	}
 	
-	fn handle_route_request(&self, sender: ATAddress, packet: &RouteRequestPacket) -> Result<(), io::Error> {
-		if packet.origin == self.address {
-			return Ok(());
-		}
-		
-		let mut seen_requests = self.seen_requests.lock()
-			.expect("should only be accessed from this thread");
-		
-		let is_new_request = seen_requests.insert((packet.origin, packet.id));
-		
-		if !is_new_request {
-			return Ok(());
-		}
-		
-		self.update_sequence_number(packet.origin_sequence);
-		
-		let mut routing_table = self.routing_table_write();
-		let mut at_module = self.at_module_write();
-		
-		if let Some(new_route) = routing_table.add_route(packet.origin, packet.origin_sequence, sender, packet.hop_count + 1) {
-			self.send_outbound_messages(&mut at_module, packet.origin, new_route)?;
-		}
+	/// Sends everything for `destination` over `route.next_hop` until unpinned, regardless of discovered routes and link failures
+	pub fn pin_route(&self, destination: ATAddress, route: StaticRoute) -> Result<(), io::Error> {
+		let is_pinned = self.query(move |protocol, now| Some(protocol.pin_route(destination, route, now)))
+			.ok_or(ErrorKind::BrokenPipe)?;
 		
-		if let Some(route) = routing_table.get_route(packet.destination, packet.destination_sequence) {
-			let sequence = if packet.destination == self.address {
-				self.current_sequence_number.fetch_add(1, Ordering::Relaxed)
-			} else {
-				route.destination_sequence
-			};
-			
-			let reply = RouteReplyPacket {
-				hop_count: route.hop_count,
-				request_destination: packet.destination,
-				request_destination_sequence: sequence,
-				request_origin: Some(packet.origin),
-			};
-			
-			at_module.send(sender, &reply.to_bytes())?;
-			
-			return Ok(());
+		if !is_pinned {
+			return Err(io::Error::new(ErrorKind::InvalidInput, "Can't pin a route to this node"));
 		}
 		
-		let packet = RouteRequestPacket {
-			hop_count: packet.hop_count + 1,
-			..*packet
-		};
-		
-		at_module.broadcast(&packet.to_bytes())?;
-		
 		Ok(())
 	}
 	
-	fn handle_route_reply(&self, sender: ATAddress, packet: &RouteReplyPacket) -> Result<(), io::Error> {
-		self.update_sequence_number(packet.request_destination_sequence);
-		
-		let mut routing_table = self.routing_table_write();
-		
-		if let Some(new_route) = routing_table.add_route(packet.request_destination, packet.request_destination_sequence, sender, packet.hop_count + 1) {
-			let mut at_module = self.at_module_write();
-			self.send_outbound_messages(&mut at_module, packet.request_destination, new_route)?;
-		}
-		
-		// 'Hello' RouteReplyPackets should not be forwarded
-		let Some(request_origin) = packet.request_origin else {
-			return Ok(());
-		};
-		
-		// RouteReplyPackets for self don't need to be forwarded
-		if request_origin == self.address {
-			return Ok(());
-		}
-		
-		let mut at_module = self.at_module_write();
-		
-		let Some(route) = routing_table.get_route(request_origin, None) else {
-			eprintln!("[WARNING] Received RouteReplyPacket for unknown request origin:\n{packet:#?}");
-			
-			let packet = RouteErrorPacket {
-				destination: request_origin,
-			};
-			
-			at_module.broadcast(&packet.to_bytes())?;
-			return Ok(());
-		};
-		
-		let packet = RouteReplyPacket {
-			hop_count: packet.hop_count + 1,
-			..*packet
-		};
-		
-		at_module.send(route.next_hop, &packet.to_bytes())?;
-		
-		Ok(())
+	/// Goes back to discovered routes for `destination`, returning the route that was pinned
+	pub fn unpin_route(&self, destination: ATAddress) -> Option<StaticRoute> {
+		self.query(move |protocol, now| protocol.unpin_route(destination, now))
 	}
 	
-	fn handle_route_error(&self, sender: ATAddress, packet: &RouteErrorPacket) -> Result<(), io::Error> {
-		let mut routing_table = self.routing_table_write();
-		
-		let is_route_removed = routing_table.remove_route(packet.destination, sender);
-		
-		if !is_route_removed {
-			// no changes were made, so no need to notify others
-			return Ok(());
-		}
-		
-		let mut at_module = self.at_module_write();
-		
-		at_module.broadcast(&packet.to_bytes())?;
-		
-		Ok(())
+	/// Copy of the routing table, only fails if the event loop stopped
+	pub fn routing_table(&self) -> Result<RoutingSnapshot, io::Error> {
+		self.query(|protocol, now| Some(protocol.routing_snapshot(now)))
+			.ok_or(ErrorKind::BrokenPipe.into())
 	}
 	
-	fn handle_data(&self, packet: &DataPacket) -> Result<(), io::Error> {
-		if packet.destination == self.address {
-			let data_callback = &self.data_callback;
-			data_callback(packet.origin, &packet.payload);
-			
-			return Ok(());
-		}
-		
-		let routing_table = self.routing_table_read();
-		let mut at_module = self.at_module_write();
-		
-		let Some(route) = routing_table.get_route(packet.destination, None) else {
-			eprintln!("[WARNING] Received DataPacket for unknown destination:\n{packet:#?}");
-			
-			let packet = RouteErrorPacket {
-				destination: packet.destination,
-			};
-			
-			at_module.broadcast(&packet.to_bytes())?;
-			return Ok(());
-		};
-		
-		at_module.send(route.next_hop, &packet.to_bytes())?;
-		
-		Ok(())
+	/// Number of stored routes, not counting routes to neighbors over the direct link
+	pub fn route_count(&self) -> usize {
+		self.query(|protocol, _| protocol.route_count())
+	}
+	
+	pub fn queue_depths(&self) -> QueueDepths {
+		self.query(|protocol, _| protocol.queue_depths())
+	}
+	
+	/// Counters of the protocol and the AT module, only fails if the event loop stopped
+	pub fn stats(&self) -> Result<ControllerStats, io::Error> {
+		let protocol = self.query(|protocol, _| Some(protocol.stats().clone()))
+			.ok_or(ErrorKind::BrokenPipe)?;
+		
+		Ok(ControllerStats {
+			protocol,
+			at_module: self.at_counters.snapshot(),
+		})
+	}
+	
+	/// Starts counting from zero, returning the counters up to now
+	pub fn reset_stats(&self) -> Result<ControllerStats, io::Error> {
+		let protocol = self.query(|protocol, now| Some(protocol.reset_stats(now)))
+			.ok_or(ErrorKind::BrokenPipe)?;
+		
+		Ok(ControllerStats {
+			protocol,
+			at_module: self.at_counters.reset(),
+		})
+	}
+	
+	/// Changes the metadata advertised in the following hellos, for example to report a new battery level
+	pub fn set_node_info(&self, node_info: NodeInfo) -> Result<(), io::Error> {
+		self.command(Command::SetNodeInfo(node_info))
+	}
+	
+	/// Ignores frames from the senders the filter doesn't allow, for example to emulate a topology where all radios hear each other
+	pub fn set_neighbor_filter(&self, neighbor_filter: NeighborFilter) -> Result<(), io::Error> {
+		self.command(Command::SetNeighborFilter(neighbor_filter))
+	}
+	
+	pub fn send(&self, address: ATAddress, data: Box<[u8]>) -> Result<DeliveryHandle, io::Error> {
+		self.start_delivery(address, data, false)
+	}
+	
+	/// Like `send`, but retransmits the data until the destination acknowledges it
+	pub fn send_reliable(&self, address: ATAddress, data: Box<[u8]>) -> Result<DeliveryHandle, io::Error> {
+		self.start_delivery(address, data, true)
+	}
+	
+	/// Only fails if the event loop stopped, transmission errors are reported through the handle
+	fn start_delivery(&self, destination: ATAddress, data: Box<[u8]>, reliable: bool) -> Result<DeliveryHandle, io::Error> {
+		let id = self.current_data_id.fetch_add(1, Ordering::Relaxed);
+		let handle = DeliveryHandle::new(id, destination, reliable);
+		
+		self.command(Command::Send {
+			destination,
+			payload: data,
+			handle: handle.clone(),
+		})?;
+		
+		Ok(handle)
+	}
+}
+
+impl Drop for AODVController {
+	fn drop(&mut self) {
+		self.stop(ShutdownConfig::default());
 	}
 }
 
@@ -0,0 +1,376 @@
+use std::{collections::{BTreeMap, BTreeSet}, future::{self, Future}, io::{self, ErrorKind}, sync::atomic::{AtomicU16, Ordering}, time::Instant};
+
+use tokio::{sync::{mpsc::{self, UnboundedReceiver, UnboundedSender, error::TrySendError}, oneshot}, task::JoinHandle};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use tracing::{debug, error, warn};
+
+use crate::at_module::{at_address::ATAddress, ATCounters, AsyncATModule};
+
+use super::{
+	delivery::{DeliveryHandle, DeliveryStatus},
+	event_loop::{Command, EventLoop, Transmission},
+	events::AODVEvent,
+	message_queue::{ReceivedMessage, Subscriber},
+	stats::{ControllerStats, QueueDepths},
+	AODVParameters, AODVProtocol, NeighborFilter, NeighborStats, NodeInfo, RoutingSnapshot, StaticRoute, RouteMetric, ShutdownConfig,
+};
+
+/// Never waits for the stream to be polled, values that don't fit are dropped
+impl<T: Send> Subscriber<T> for mpsc::Sender<T> {
+	fn send(&self, value: T) -> bool {
+		match self.try_send(value) {
+			Ok(()) => true,
+			Err(TrySendError::Full(_)) => {
+				warn!("Stream is full, dropping value");
+				true
+			},
+			Err(TrySendError::Closed(_)) => false,
+		}
+	}
+}
+
+/// Runs an `AODVProtocol` as a tokio task, sharing the event loop with `AODVController`
+///
+/// Must be started from within a tokio runtime.
+/// Dropping the controller shuts it down with the default `ShutdownConfig` in the background.
+pub struct AsyncAODVController {
+	commands: UnboundedSender<Command>,
+	current_data_id: AtomicU16,
+	at_counters: ATCounters,
+	task: Option<JoinHandle<()>>,
+}
+
+impl AsyncAODVController {
+	/// Fails with `ErrorKind::InvalidInput` if the parameters are not valid, see `AODVParameters::validate`
+	pub fn start(at_module: AsyncATModule, parameters: AODVParameters, metric: Box<dyn RouteMetric>, node_info: NodeInfo) -> Result<Self, io::Error> {
+		let protocol = AODVProtocol::new(at_module.address(), parameters, metric, node_info, Instant::now())
+			.map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+		
+		let (command_sender, command_receiver) = mpsc::unbounded_channel();
+		let at_counters = at_module.counters();
+		
+		// received data is only passed to the streams from `messages`
+		let event_loop = EventLoop::new(protocol, |_: ATAddress, _: &[u8]| ());
+		
+		let task = tokio::spawn(run(event_loop, at_module, command_receiver));
+		
+		Ok(AsyncAODVController {
+			commands: command_sender,
+			// random, so a restarted node's messages aren't taken for duplicates of the ones it sent before
+			current_data_id: rand::random::<u16>().into(),
+			at_counters,
+			task: Some(task),
+		})
+	}
+	
+	/// Stops the event loop and waits for it to finish
+	pub async fn shutdown(mut self, config: ShutdownConfig) {
+		// the event loop might already be gone, the task is awaited either way
+		let _ = self.command(Command::Shutdown(config));
+		
+		if let Some(task) = self.task.take() {
+			task.await
+				.expect("no tasks should panic");
+		}
+	}
+	
+	fn command(&self, command: Command) -> Result<(), io::Error> {
+		self.commands.send(command)
+			.map_err(|_| ErrorKind::BrokenPipe.into())
+	}
+	
+	/// Asks the event loop for a value, which is the default if the event loop stopped
+	async fn query<T: Default + Send + 'static>(&self, query: impl FnOnce(&mut AODVProtocol, Instant) -> T + Send + 'static) -> T {
+		let (sender, receiver) = oneshot::channel();
+		
+		let command = Command::Query(Box::new(move |protocol, now| {
+			// the caller might have stopped waiting
+			let _ = sender.send(query(protocol, now));
+		}));
+		
+		if self.command(command).is_err() {
+			return T::default();
+		}
+		
+		receiver.await
+			.unwrap_or_default()
+	}
+	
+	/// Stream of data received from now on, dropping messages while `capacity` of them wait to be polled
+	pub fn messages(&self, capacity: usize) -> Result<impl Stream<Item = ReceivedMessage> + use<>, io::Error> {
+		let (sender, receiver) = mpsc::channel(capacity);
+		
+		self.command(Command::Subscribe(Box::new(sender)))?;
+		
+		Ok(ReceiverStream::new(receiver))
+	}
+	
+	/// Stream of routing events from now on, dropping events while `capacity` of them wait to be polled
+	pub fn events(&self, capacity: usize) -> Result<impl Stream<Item = AODVEvent> + use<>, io::Error> {
+		let (sender, receiver) = mpsc::channel(capacity);
+		
+		self.command(Command::SubscribeEvents(Box::new(sender)))?;
+		
+		Ok(ReceiverStream::new(receiver))
+	}
+	
+	/// Link statistics of all nodes this node received frames from recently
+	pub async fn neighbor_stats(&self) -> Vec<NeighborStats> {
+		self.query(|protocol, now| protocol.neighbor_stats(now)).await
+	}
+	
+	/// Nodes that are not neighbors but can be reached over one, each with the neighbors that can reach it
+	pub async fn two_hop_neighbors(&self) -> BTreeMap<ATAddress, BTreeSet<ATAddress>> {
+		self.query(|protocol, _| protocol.two_hop_neighbors()).await
+	}
+	
+	/// Neighbors whose route requests are dropped for exceeding the rate limit, with the time they are dropped until
+	pub async fn throttled_neighbors(&self) -> Vec<(ATAddress, Instant)> {
+		self.query(|protocol, now| protocol.throttled_neighbors(now)).await
+	}
+	
+	/// Sends everything for `destination` over `route.next_hop` until unpinned, regardless of discovered routes and link failures
+	pub async fn pin_route(&self, destination: ATAddress, route: StaticRoute) -> Result<(), io::Error> {
+		let is_pinned = self.query(move |protocol, now| Some(protocol.pin_route(destination, route, now))).await
+			.ok_or(ErrorKind::BrokenPipe)?;
+		
+		if !is_pinned {
+			return Err(io::Error::new(ErrorKind::InvalidInput, "Can't pin a route to this node"));
+		}
+		
+		Ok(())
+	}
+	
+	/// Goes back to discovered routes for `destination`, returning the route that was pinned
+	pub async fn unpin_route(&self, destination: ATAddress) -> Option<StaticRoute> {
+		self.query(move |protocol, now| protocol.unpin_route(destination, now)).await
+	}
+	
+	/// Copy of the routing table, only fails if the event loop stopped
+	pub async fn routing_table(&self) -> Result<RoutingSnapshot, io::Error> {
+		self.query(|protocol, now| Some(protocol.routing_snapshot(now))).await
+			.ok_or(ErrorKind::BrokenPipe.into())
+	}
+	
+	/// Number of stored routes, not counting routes to neighbors over the direct link
+	pub async fn route_count(&self) -> usize {
+		self.query(|protocol, _| protocol.route_count()).await
+	}
+	
+	pub async fn queue_depths(&self) -> QueueDepths {
+		self.query(|protocol, _| protocol.queue_depths()).await
+	}
+	
+	/// Counters of the protocol and the AT module, only fails if the event loop stopped
+	pub async fn stats(&self) -> Result<ControllerStats, io::Error> {
+		let protocol = self.query(|protocol, _| Some(protocol.stats().clone())).await
+			.ok_or(ErrorKind::BrokenPipe)?;
+		
+		Ok(ControllerStats {
+			protocol,
+			at_module: self.at_counters.snapshot(),
+		})
+	}
+	
+	/// Starts counting from zero, returning the counters up to now
+	pub async fn reset_stats(&self) -> Result<ControllerStats, io::Error> {
+		let protocol = self.query(|protocol, now| Some(protocol.reset_stats(now))).await
+			.ok_or(ErrorKind::BrokenPipe)?;
+		
+		Ok(ControllerStats {
+			protocol,
+			at_module: self.at_counters.reset(),
+		})
+	}
+	
+	/// Changes the metadata advertised in the following hellos, for example to report a new battery level
+	pub fn set_node_info(&self, node_info: NodeInfo) -> Result<(), io::Error> {
+		self.command(Command::SetNodeInfo(node_info))
+	}
+	
+	/// Ignores frames from the senders the filter doesn't allow, for example to emulate a topology where all radios hear each other
+	pub fn set_neighbor_filter(&self, neighbor_filter: NeighborFilter) -> Result<(), io::Error> {
+		self.command(Command::SetNeighborFilter(neighbor_filter))
+	}
+	
+	/// Resolves once the data was transmitted or failed to be
+	pub async fn send(&self, address: ATAddress, data: Box<[u8]>) -> Result<DeliveryStatus, io::Error> {
+		let handle = self.start_delivery(address, data, false)?;
+		
+		Ok(handle.outcome().await)
+	}
+	
+	/// Like `send`, but retransmits the data until the destination acknowledges it
+	pub async fn send_reliable(&self, address: ATAddress, data: Box<[u8]>) -> Result<DeliveryStatus, io::Error> {
+		let handle = self.start_delivery(address, data, true)?;
+		
+		Ok(handle.outcome().await)
+	}
+	
+	/// Only fails if the event loop stopped, transmission errors are reported through the handle
+	fn start_delivery(&self, destination: ATAddress, data: Box<[u8]>, reliable: bool) -> Result<DeliveryHandle, io::Error> {
+		let id = self.current_data_id.fetch_add(1, Ordering::Relaxed);
+		let handle = DeliveryHandle::new(id, destination, reliable);
+		
+		self.command(Command::Send {
+			destination,
+			payload: data,
+			handle: handle.clone(),
+		})?;
+		
+		Ok(handle)
+	}
+}
+
+impl Drop for AsyncAODVController {
+	fn drop(&mut self) {
+		if self.task.is_some() {
+			let _ = self.command(Command::Shutdown(ShutdownConfig::default()));
+		}
+	}
+}
+
+/// Handles commands, frames and timers until shut down or the controller is gone
+async fn run<C: FnMut(ATAddress, &[u8])>(mut event_loop: EventLoop<C>, mut at_module: AsyncATModule, mut commands: UnboundedReceiver<Command>) {
+	// false once the serial port was closed
+	let mut receiving = true;
+	
+	while event_loop.perform_actions(&mut |transmission| transmit(&at_module, transmission)) {
+		let deadline = event_loop.next_deadline();
+		
+		let command = tokio::select! {
+			command = commands.recv() => match command {
+				Some(command) => Some(command),
+				None => return,
+			},
+			message = at_module.recv(), if receiving => match message {
+				Some(message) => {
+					debug!(sender = %message.address, "Received message:\n\t{message}");
+					Some(Command::Frame(message))
+				},
+				None => {
+					receiving = false;
+					None
+				},
+			},
+			() = sleep_until(deadline) => None,
+		};
+		
+		event_loop.handle(command, Instant::now());
+	}
+}
+
+fn transmit(at_module: &AsyncATModule, transmission: Transmission) {
+	// queued in order right away, only the result is awaited in the background
+	match transmission {
+		Transmission::Unicast { next_hop, frame } => log_errors(at_module.send(next_hop, frame)),
+		Transmission::Broadcast { frame } => log_errors(at_module.broadcast(frame)),
+	}
+}
+
+fn log_errors(result: impl Future<Output = Result<(), io::Error>> + Send + 'static) {
+	tokio::spawn(async move {
+		if let Err(err) = result.await {
+			error!(%err, "Could not send frame");
+		}
+	});
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+	match deadline {
+		Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+		None => future::pending().await,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	
+	use tokio_stream::StreamExt;
+	
+	use crate::{
+		aodv::{packets::{DataPacket, HelloPacket, PacketType}, HopCount},
+		at_module::{ATMessage, MockRadio},
+	};
+	
+	use super::*;
+	
+	fn address(address: &[u8; 4]) -> ATAddress {
+		ATAddress::new(*address).unwrap()
+	}
+	
+	async fn next_frame(radio: &mut MockRadio) -> (ATAddress, Option<PacketType>) {
+		let (destination, frame) = tokio::time::timeout(Duration::from_secs(5), radio.sent.recv()).await
+			.expect("a frame should be sent")
+			.expect("the module should still be open");
+		
+		(destination, PacketType::of_frame(&frame))
+	}
+	
+	fn receive(radio: &MockRadio, sender: ATAddress, data: Box<[u8]>) {
+		radio.received.send(ATMessage {
+			address: sender,
+			data,
+			signal: None,
+		}).unwrap();
+	}
+	
+	#[tokio::test]
+	async fn drives_protocol_through_event_loop() {
+		let own_address = address(b"0001");
+		let neighbor = address(b"0002");
+		
+		let (at_module, mut radio) = AsyncATModule::mock(own_address);
+		let parameters = AODVParameters {
+			link_acks: None,
+			..AODVParameters::lora()
+		};
+		
+		let controller = AsyncAODVController::start(at_module, parameters, Box::new(HopCount), NodeInfo::default()).unwrap();
+		let mut events = controller.events(8).unwrap();
+		let mut messages = controller.messages(8).unwrap();
+		
+		// the first hello is due right away
+		assert_eq!(next_frame(&mut radio).await, (ATAddress::BROADCAST, Some(PacketType::Hello)));
+		
+		let hello = HelloPacket {
+			sequence: 1,
+			interval: Duration::from_secs(10),
+			info: NodeInfo::default(),
+			neighbors: Vec::new(),
+		};
+		receive(&radio, neighbor, hello.to_bytes());
+		
+		assert_eq!(events.next().await, Some(AODVEvent::NeighborUp(neighbor)));
+		
+		let data = DataPacket {
+			id: 7,
+			ack_requested: false,
+			hop_count: 0,
+			destination: own_address,
+			origin: neighbor,
+			payload: b"ping"[..].into(),
+		};
+		receive(&radio, neighbor, data.to_bytes());
+		
+		let message = messages.next().await.unwrap();
+		assert_eq!((message.origin, &*message.payload), (neighbor, &b"ping"[..]));
+		
+		// the neighbor is a route of its own
+		let status = controller.send(neighbor, b"pong"[..].into()).await.unwrap();
+		assert_eq!(status, DeliveryStatus::Transmitted);
+		assert_eq!(next_frame(&mut radio).await, (neighbor, Some(PacketType::Data)));
+		
+		controller.shutdown(ShutdownConfig {
+			flush_timeout: None,
+			goodbye: true,
+		}).await;
+		
+		assert_eq!(next_frame(&mut radio).await, (ATAddress::BROADCAST, Some(PacketType::RouteError)));
+		
+		// the streams end with the event loop
+		assert_eq!(messages.next().await, None);
+		assert!(events.all(|event| !matches!(event, AODVEvent::NeighborUp(_))).await);
+	}
+}
\ No newline at end of file
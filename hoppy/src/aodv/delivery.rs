@@ -0,0 +1,259 @@
+use std::{future::{self, Future}, sync::{Arc, Mutex, Condvar, MutexGuard}, task::{Poll, Waker}, time::{Duration, Instant}};
+
+use crate::at_module::at_address::ATAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+	/// Waiting for a route to the destination
+	Queued,
+	/// A route to the destination is known, the data is being transmitted
+	RouteFound,
+	/// The data was transmitted to the next hop
+	Transmitted,
+	/// The destination acknowledged the data, only reported for reliable deliveries
+	Acknowledged,
+	Failed(DeliveryFailure),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DeliveryFailure {
+	/// No route to the destination could be found
+	NoRoute,
+	/// The next hop did not acknowledge the data
+	LinkBroken,
+	/// The destination did not acknowledge the data in time
+	Timeout,
+	/// The controller shut down before the delivery finished
+	Shutdown,
+	/// Too much data was waiting for routes already
+	QueueFull,
+}
+
+/// Whether a delivery with this status will not change anymore
+pub(super) fn is_final_status(status: DeliveryStatus, reliable: bool) -> bool {
+	match status {
+		DeliveryStatus::Failed(_) | DeliveryStatus::Acknowledged => true,
+		// without acknowledgements from the destination there is nothing more to report
+		DeliveryStatus::Transmitted => !reliable,
+		DeliveryStatus::Queued | DeliveryStatus::RouteFound => false,
+	}
+}
+
+struct DeliveryState {
+	status: Mutex<DeliveryStatus>,
+	status_changed: Condvar,
+	/// Tasks awaiting the outcome, locked after the status
+	wakers: Mutex<Vec<Waker>>,
+	reliable: bool,
+}
+
+/// Tracks the progress of data passed to `AODVController::send`
+#[derive(Clone)]
+pub struct DeliveryHandle {
+	id: u16,
+	destination: ATAddress,
+	state: Arc<DeliveryState>,
+}
+
+impl DeliveryHandle {
+	pub(super) fn new(id: u16, destination: ATAddress, reliable: bool) -> Self {
+		Self {
+			id,
+			destination,
+			state: Arc::new(DeliveryState {
+				status: Mutex::new(DeliveryStatus::Queued),
+				status_changed: Condvar::new(),
+				wakers: Mutex::new(Vec::new()),
+				reliable,
+			}),
+		}
+	}
+	
+	pub fn id(&self) -> u16 {
+		self.id
+	}
+	
+	pub fn destination(&self) -> ATAddress {
+		self.destination
+	}
+	
+	pub fn is_reliable(&self) -> bool {
+		self.state.reliable
+	}
+	
+	fn status_lock(&self) -> MutexGuard<'_, DeliveryStatus> {
+		self.state.status.lock()
+			.expect("no threads should panic")
+	}
+	
+	pub fn status(&self) -> DeliveryStatus {
+		*self.status_lock()
+	}
+	
+	/// Whether the status will not change anymore
+	pub fn is_finished(&self) -> bool {
+		self.is_final(self.status())
+	}
+	
+	fn is_final(&self, status: DeliveryStatus) -> bool {
+		is_final_status(status, self.state.reliable)
+	}
+	
+	/// Blocks until the delivery succeeded or failed
+	pub fn wait(&self) -> DeliveryStatus {
+		let status = self.state.status_changed.wait_while(self.status_lock(), |status| !self.is_final(*status))
+			.expect("no threads should panic");
+		
+		*status
+	}
+	
+	/// Resolves once the delivery succeeded or failed, without blocking a thread
+	pub fn outcome(&self) -> impl Future<Output = DeliveryStatus> + '_ {
+		future::poll_fn(|context| {
+			let status = self.status_lock();
+			
+			if self.is_final(*status) {
+				return Poll::Ready(*status);
+			}
+			
+			// registered while holding the status lock, so no update can be missed
+			let mut wakers = self.wakers_lock();
+			
+			if !wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+				wakers.push(context.waker().clone());
+			}
+			
+			Poll::Pending
+		})
+	}
+	
+	fn wakers_lock(&self) -> MutexGuard<'_, Vec<Waker>> {
+		self.state.wakers.lock()
+			.expect("no threads should panic")
+	}
+	
+	/// Blocks until the delivery succeeded or failed, or the timeout runs out
+	pub fn wait_timeout(&self, timeout: Duration) -> DeliveryStatus {
+		let deadline = Instant::now() + timeout;
+		let mut status = self.status_lock();
+		
+		while !self.is_final(*status) {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			
+			if remaining.is_zero() {
+				break;
+			}
+			
+			(status, _) = self.state.status_changed.wait_timeout(status, remaining)
+				.expect("no threads should panic");
+		}
+		
+		*status
+	}
+	
+	pub(super) fn update(&self, new_status: DeliveryStatus) {
+		let mut status = self.status_lock();
+		
+		if self.is_final(*status) {
+			return;
+		}
+		
+		*status = new_status;
+		self.state.status_changed.notify_all();
+		
+		for waker in self.wakers_lock().drain(..) {
+			waker.wake();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{pin::pin, sync::atomic::{AtomicBool, Ordering}, task::{Context, Wake}, thread};
+	
+	use super::*;
+	
+	fn address() -> ATAddress {
+		ATAddress::new(*b"0002").unwrap()
+	}
+	
+	#[test]
+	fn unreliable_finishes_when_transmitted() {
+		let handle = DeliveryHandle::new(0, address(), false);
+		
+		assert_eq!(handle.status(), DeliveryStatus::Queued);
+		
+		handle.update(DeliveryStatus::RouteFound);
+		assert!(!handle.is_finished());
+		
+		handle.update(DeliveryStatus::Transmitted);
+		assert!(handle.is_finished());
+	}
+	
+	#[test]
+	fn reliable_finishes_when_acknowledged() {
+		let handle = DeliveryHandle::new(0, address(), true);
+		
+		handle.update(DeliveryStatus::Transmitted);
+		assert!(!handle.is_finished());
+		
+		handle.update(DeliveryStatus::Acknowledged);
+		assert!(handle.is_finished());
+	}
+	
+	#[test]
+	fn final_status_does_not_change() {
+		let handle = DeliveryHandle::new(0, address(), true);
+		
+		handle.update(DeliveryStatus::Failed(DeliveryFailure::Timeout));
+		handle.update(DeliveryStatus::Acknowledged);
+		
+		assert_eq!(handle.status(), DeliveryStatus::Failed(DeliveryFailure::Timeout));
+	}
+	
+	#[test]
+	fn wait_for_other_thread() {
+		let handle = DeliveryHandle::new(0, address(), true);
+		let updater = handle.clone();
+		
+		thread::spawn(move || {
+			updater.update(DeliveryStatus::Transmitted);
+			updater.update(DeliveryStatus::Acknowledged);
+		});
+		
+		assert_eq!(handle.wait(), DeliveryStatus::Acknowledged);
+	}
+	
+	#[test]
+	fn outcome_wakes_task() {
+		struct Flag(AtomicBool);
+		
+		impl Wake for Flag {
+			fn wake(self: Arc<Self>) {
+				self.0.store(true, Ordering::Relaxed);
+			}
+		}
+		
+		let handle = DeliveryHandle::new(0, address(), false);
+		let flag = Arc::new(Flag(AtomicBool::new(false)));
+		let waker = Waker::from(Arc::clone(&flag));
+		let mut context = Context::from_waker(&waker);
+		
+		let mut outcome = pin!(handle.outcome());
+		assert_eq!(outcome.as_mut().poll(&mut context), Poll::Pending);
+		
+		handle.update(DeliveryStatus::Transmitted);
+		
+		assert!(flag.0.load(Ordering::Relaxed));
+		assert_eq!(outcome.poll(&mut context), Poll::Ready(DeliveryStatus::Transmitted));
+	}
+	
+	#[test]
+	fn wait_timeout_returns_current_status() {
+		let handle = DeliveryHandle::new(0, address(), true);
+		
+		handle.update(DeliveryStatus::Transmitted);
+		
+		assert_eq!(handle.wait_timeout(Duration::from_millis(10)), DeliveryStatus::Transmitted);
+	}
+}
\ No newline at end of file
@@ -0,0 +1,317 @@
+use std::{collections::BTreeMap, sync::mpsc::{Receiver, RecvTimeoutError, Sender}, time::Instant};
+
+use tracing::error;
+
+use crate::at_module::{ATMessage, ATModule, at_address::ATAddress};
+
+use super::{
+	delivery::{DeliveryHandle, DeliveryFailure, DeliveryStatus},
+	events::AODVEvent,
+	message_queue::{ReceivedMessage, Subscriber},
+	neighbor_filter::NeighborFilter,
+	node_info::NodeInfo,
+	protocol::{AODVProtocol, Action, Timer},
+	ShutdownConfig,
+};
+
+/// Reads the protocol state, sending the answer back over a channel captured by the closure
+pub(super) type Query = Box<dyn FnOnce(&mut AODVProtocol, Instant) + Send>;
+
+/// Messages to the event loop, the only place the protocol state is accessed
+pub(super) enum Command {
+	Frame(ATMessage),
+	Send {
+		destination: ATAddress,
+		payload: Box<[u8]>,
+		handle: DeliveryHandle,
+	},
+	SetNodeInfo(NodeInfo),
+	SetNeighborFilter(NeighborFilter),
+	Query(Query),
+	/// Passes received messages to a queue as well as the data callback
+	Subscribe(Box<dyn Subscriber<ReceivedMessage>>),
+	/// Passes routing and neighbor events to a queue
+	SubscribeEvents(Box<dyn Subscriber<AODVEvent>>),
+	Shutdown(ShutdownConfig),
+}
+
+/// Frames waiting for the serial port, which is written on its own thread so slow writes don't stall packet handling
+pub(super) enum Transmission {
+	Unicast {
+		next_hop: ATAddress,
+		frame: Box<[u8]>,
+	},
+	Broadcast {
+		frame: Box<[u8]>,
+	},
+}
+
+struct PendingShutdown {
+	/// Until when pending data is given a chance to be delivered
+	deadline: Instant,
+	goodbye: bool,
+}
+
+/// State shared by the threaded and the async driver, which only differ in how they wait for commands and timers
+pub(super) struct EventLoop<C: FnMut(ATAddress, &[u8])> {
+	protocol: AODVProtocol,
+	/// When each timer of the protocol fires next
+	timers: BTreeMap<Timer, Instant>,
+	deliveries: BTreeMap<(ATAddress, u16), DeliveryHandle>,
+	shutdown: Option<PendingShutdown>,
+	/// Dropped when the event loop stops, which closes their queues
+	subscribers: Vec<Box<dyn Subscriber<ReceivedMessage>>>,
+	event_subscribers: Vec<Box<dyn Subscriber<AODVEvent>>>,
+	data_callback: C,
+}
+
+impl<C: FnMut(ATAddress, &[u8])> EventLoop<C> {
+	pub fn new(protocol: AODVProtocol, data_callback: C) -> Self {
+		Self {
+			protocol,
+			timers: BTreeMap::new(),
+			deliveries: BTreeMap::new(),
+			shutdown: None,
+			subscribers: Vec::new(),
+			event_subscribers: Vec::new(),
+			data_callback,
+		}
+	}
+	
+	/// Handles commands and timers until shut down or all command senders are gone
+	pub fn run(mut self, commands: Receiver<Command>, transmissions: Sender<Transmission>) {
+		let mut transmit = |transmission| {
+			if transmissions.send(transmission).is_err() {
+				error!("Serial writer stopped, dropping frame");
+			}
+		};
+		
+		while self.perform_actions(&mut transmit) {
+			let command = match self.next_deadline() {
+				Some(deadline) => match commands.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+					Ok(command) => Some(command),
+					Err(RecvTimeoutError::Timeout) => None,
+					Err(RecvTimeoutError::Disconnected) => return,
+				},
+				None => match commands.recv() {
+					Ok(command) => Some(command),
+					Err(_) => return,
+				},
+			};
+			
+			self.handle(command, Instant::now());
+		}
+	}
+	
+	/// When the event loop has to wake up if no command arrives
+	pub fn next_deadline(&self) -> Option<Instant> {
+		let shutdown_deadline = self.shutdown.as_ref()
+			.map(|shutdown| shutdown.deadline);
+		
+		self.timers.values()
+			.copied()
+			.chain(shutdown_deadline)
+			.min()
+	}
+	
+	/// Handles the command, if any, and all timers that are due
+	pub fn handle(&mut self, command: Option<Command>, now: Instant) {
+		if let Some(command) = command {
+			self.handle_command(command, now);
+		}
+		
+		self.fire_due_timers(now);
+	}
+	
+	fn handle_command(&mut self, command: Command, now: Instant) {
+		match command {
+			Command::Frame(message) => self.protocol.handle_frame(&message, now),
+			Command::Send { destination, payload, handle } => {
+				self.protocol.send(destination, handle.id(), payload, handle.is_reliable(), now);
+				self.deliveries.insert((destination, handle.id()), handle);
+			},
+			Command::SetNodeInfo(node_info) => self.protocol.set_node_info(node_info),
+			Command::SetNeighborFilter(neighbor_filter) => self.protocol.set_neighbor_filter(neighbor_filter, now),
+			Command::Query(query) => query(&mut self.protocol, now),
+			Command::Subscribe(subscriber) => self.subscribers.push(subscriber),
+			Command::SubscribeEvents(subscriber) => self.event_subscribers.push(subscriber),
+			Command::Shutdown(config) => {
+				self.shutdown = Some(PendingShutdown {
+					deadline: now + config.flush_timeout.unwrap_or_default(),
+					goodbye: config.goodbye,
+				});
+			},
+		}
+	}
+	
+	fn fire_due_timers(&mut self, now: Instant) {
+		let due_timers: Vec<_> = self.timers.iter()
+			.filter(|(_, deadline)| now >= **deadline)
+			.map(|(&timer, _)| timer)
+			.collect();
+		
+		for timer in due_timers {
+			self.timers.remove(&timer);
+			self.protocol.handle_timer(timer, now);
+		}
+	}
+	
+	/// Performs the actions the protocol emitted so far, returning false once the event loop finished shutting down
+	pub fn perform_actions(&mut self, transmit: &mut impl FnMut(Transmission)) -> bool {
+		// includes the first timers, scheduled when the protocol was created
+		self.perform_protocol_actions(transmit);
+		
+		let Some(shutdown) = &self.shutdown else {
+			return true;
+		};
+		
+		let now = Instant::now();
+		
+		if now < shutdown.deadline && self.protocol.has_pending_data() {
+			return true;
+		}
+		
+		let goodbye = shutdown.goodbye;
+		self.finish(goodbye, now, transmit);
+		
+		false
+	}
+	
+	/// Sends the goodbye and fails all unfinished deliveries
+	fn finish(&mut self, goodbye: bool, now: Instant, transmit: &mut impl FnMut(Transmission)) {
+		if goodbye {
+			self.protocol.say_goodbye(now);
+			self.perform_protocol_actions(transmit);
+		}
+		
+		for handle in self.deliveries.values() {
+			handle.update(DeliveryStatus::Failed(DeliveryFailure::Shutdown));
+		}
+	}
+	
+	fn perform_protocol_actions(&mut self, transmit: &mut impl FnMut(Transmission)) {
+		while let Some(action) = self.protocol.poll_action() {
+			match action {
+				Action::Send { next_hop, frame } => transmit(Transmission::Unicast {
+					next_hop,
+					frame,
+				}),
+				Action::Broadcast { frame } => transmit(Transmission::Broadcast {
+					frame,
+				}),
+				Action::Deliver(message) => {
+					(self.data_callback)(message.origin, &message.payload);
+					
+					// forget subscribers that dropped their receiver
+					self.subscribers.retain(|subscriber| subscriber.send(message.clone()));
+				},
+				Action::Event(event) => {
+					self.event_subscribers.retain(|subscriber| subscriber.send(event.clone()));
+				},
+				Action::ScheduleTimer { timer, at } => {
+					self.timers.insert(timer, at);
+				},
+				Action::UpdateDelivery { destination, id, status } => {
+					let Some(handle) = self.deliveries.get(&(destination, id)) else {
+						continue;
+					};
+					
+					handle.update(status);
+					
+					if handle.is_finished() {
+						self.deliveries.remove(&(destination, id));
+					}
+				},
+			}
+		}
+	}
+}
+
+/// Writes queued frames to the AT module until the event loop is gone
+pub(super) fn write_transmissions(mut at_module: ATModule, transmissions: Receiver<Transmission>) {
+	for transmission in transmissions {
+		let result = match &transmission {
+			Transmission::Unicast { next_hop, frame } => at_module.send(*next_hop, frame),
+			Transmission::Broadcast { frame } => at_module.broadcast(frame),
+		};
+		
+		if let Err(err) = result {
+			error!(%err, "Could not send frame");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	
+	use super::*;
+	use super::super::{metric::HopCount, packets::PacketType, parameters::AODVParameters};
+	
+	/// Steps the event loop from deadline to deadline up to `end`, recording its broadcasts and when they were made
+	fn run_until(event_loop: &mut EventLoop<impl FnMut(ATAddress, &[u8])>, mut now: Instant, end: Instant, broadcasts: &mut Vec<(Instant, PacketType)>) {
+		loop {
+			event_loop.perform_actions(&mut |transmission| {
+				if let Transmission::Broadcast { frame } = transmission {
+					broadcasts.push((now, PacketType::of_frame(&frame).unwrap()));
+				}
+			});
+			
+			let Some(deadline) = event_loop.next_deadline().filter(|deadline| *deadline <= end) else {
+				return;
+			};
+			
+			event_loop.handle(None, deadline);
+			now = deadline;
+		}
+	}
+	
+	#[test]
+	fn fires_timers_on_schedule() {
+		let start = Instant::now();
+		let address = ATAddress::new(*b"0001").unwrap();
+		let destination = ATAddress::new(*b"0002").unwrap();
+		let parameters = AODVParameters::rfc3561();
+		let net_traversal_time = parameters.net_traversal_time();
+		
+		let protocol = AODVProtocol::new(address, parameters, Box::new(HopCount), NodeInfo::default(), start).unwrap();
+		let mut event_loop = EventLoop::new(protocol, |_, _| {});
+		let mut broadcasts = Vec::new();
+		
+		// the first hello goes out right away
+		run_until(&mut event_loop, start, start, &mut broadcasts);
+		assert_eq!(broadcasts, [(start, PacketType::Hello)]);
+		
+		let next_hello = event_loop.timers[&Timer::Hello];
+		assert!(next_hello > start && next_hello <= start + Duration::from_secs(1));
+		
+		run_until(&mut event_loop, start, next_hello, &mut broadcasts);
+		assert_eq!(broadcasts, [(start, PacketType::Hello), (next_hello, PacketType::Hello)]);
+		
+		// nobody answers the route request
+		broadcasts.clear();
+		event_loop.handle(Some(Command::Send {
+			destination,
+			payload: b"data"[..].into(),
+			handle: DeliveryHandle::new(0, destination, false),
+		}), next_hello);
+		
+		run_until(&mut event_loop, next_hello, next_hello + net_traversal_time - Duration::from_millis(1), &mut broadcasts);
+		
+		let requests: Vec<_> = broadcasts.iter()
+			.filter(|(_, packet_type)| *packet_type == PacketType::RouteRequest)
+			.map(|(time, _)| *time)
+			.collect();
+		assert_eq!(requests, [next_hello]);
+		
+		// retries are checked on the next maintenance once the request timed out
+		let retry = event_loop.timers[&Timer::Maintenance];
+		run_until(&mut event_loop, next_hello, retry, &mut broadcasts);
+		
+		let requests: Vec<_> = broadcasts.iter()
+			.filter(|(_, packet_type)| *packet_type == PacketType::RouteRequest)
+			.map(|(time, _)| *time)
+			.collect();
+		assert_eq!(requests, [next_hello, retry]);
+	}
+}
\ No newline at end of file
@@ -0,0 +1,68 @@
+use crate::at_module::at_address::ATAddress;
+
+use super::message_queue::MessageReceiver;
+
+/// Bounded queue of events, see `AODVController::event_receiver`
+pub type EventReceiver = MessageReceiver<AODVEvent>;
+
+/// Why a received packet was not handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum DropReason {
+	/// The frame could not be parsed
+	Invalid,
+	/// No route to the destination is known, so the packet could not be forwarded
+	NoRoute,
+	/// The sender exceeded the route request rate limit
+	RateLimited,
+	/// The sender is shut out by the neighbor filter
+	Filtered,
+}
+
+/// Changes of the node's view of the mesh
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AODVEvent {
+	/// A route to `destination` over a next hop it wasn't known over was learned
+	RouteAdded {
+		destination: ATAddress,
+		next_hop: ATAddress,
+		hop_count: u8,
+		metric: u16,
+	},
+	/// The sequence number or metric of a known route changed
+	RouteUpdated {
+		destination: ATAddress,
+		next_hop: ATAddress,
+		hop_count: u8,
+		metric: u16,
+	},
+	/// A route was removed because its next hop broke the link or reported the destination unreachable
+	RouteInvalidated {
+		destination: ATAddress,
+		next_hop: ATAddress,
+	},
+	/// A route was removed because its next hop stopped sending hellos, or it wasn't confirmed or used for data within the active route timeout
+	RouteExpired {
+		destination: ATAddress,
+		next_hop: ATAddress,
+	},
+	NeighborUp(ATAddress),
+	NeighborDown(ATAddress),
+	/// A route request was broadcast for data waiting for a route
+	DiscoveryStarted {
+		destination: ATAddress,
+	},
+	DiscoverySucceeded {
+		destination: ATAddress,
+		hop_count: u8,
+	},
+	/// All data waiting for a route to `destination` failed
+	DiscoveryFailed {
+		destination: ATAddress,
+	},
+	PacketDropped {
+		sender: ATAddress,
+		reason: DropReason,
+	},
+}
\ No newline at end of file
@@ -0,0 +1,199 @@
+use std::{collections::BTreeMap, time::{Duration, Instant}};
+
+use rand::Rng;
+use tracing::debug;
+
+use crate::at_module::at_address::ATAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BroadcastSuppression {
+	/// Always rebroadcast
+	None,
+	/// Skip the rebroadcast if at least `threshold` copies, including the first one, were heard before it was sent
+	Counter {
+		threshold: u8,
+	},
+	/// Rebroadcast with the given probability between 0 and 1
+	Probabilistic {
+		probability: f32,
+	},
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FloodingConfig {
+	/// Rebroadcasts are delayed by a random time up to this, so neighbors don't transmit at the same moment
+	pub max_jitter: Duration,
+	pub suppression: BroadcastSuppression,
+}
+
+/// Identifies all copies of a flooded packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FloodKey {
+	RouteRequest {
+		origin: ATAddress,
+		id: u16,
+	},
+	RouteError {
+		destination: ATAddress,
+	},
+}
+
+struct PendingBroadcast {
+	frame: Box<[u8]>,
+	deadline: Instant,
+	copies: u8,
+}
+
+pub struct BroadcastQueue {
+	config: FloodingConfig,
+	pending: BTreeMap<FloodKey, PendingBroadcast>,
+}
+
+impl BroadcastQueue {
+	pub fn new(config: FloodingConfig) -> Self {
+		Self {
+			config,
+			pending: BTreeMap::new(),
+		}
+	}
+	
+	/// Returns whether the rebroadcast was scheduled rather than suppressed right away
+	pub fn schedule(&mut self, key: FloodKey, frame: Box<[u8]>, now: Instant, rng: &mut impl Rng) -> bool {
+		if let BroadcastSuppression::Probabilistic { probability } = self.config.suppression {
+			if !rng.gen_bool(probability.clamp(0.0, 1.0) as f64) {
+				return false;
+			}
+		}
+		
+		let jitter = if self.config.max_jitter.is_zero() {
+			Duration::ZERO
+		} else {
+			rng.gen_range(Duration::ZERO..self.config.max_jitter)
+		};
+		
+		self.pending.insert(key, PendingBroadcast {
+			frame,
+			deadline: now + jitter,
+			copies: 1,
+		});
+		
+		true
+	}
+	
+	/// Counts another copy of a flooded packet heard while its rebroadcast is pending
+	pub fn overheard(&mut self, key: FloodKey) {
+		if let Some(pending) = self.pending.get_mut(&key) {
+			pending.copies = pending.copies.saturating_add(1);
+		}
+	}
+	
+	/// Number of packets waiting to be rebroadcast
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+	
+	pub fn next_deadline(&self) -> Option<Instant> {
+		self.pending.values()
+			.map(|pending| pending.deadline)
+			.min()
+	}
+	
+	/// Removes the broadcasts that are due, returning those that weren't suppressed
+	pub fn take_due(&mut self, now: Instant) -> Vec<Box<[u8]>> {
+		let due_keys: Vec<_> = self.pending.iter()
+			.filter(|(_, pending)| now >= pending.deadline)
+			.map(|(&key, _)| key)
+			.collect();
+		
+		let mut frames = Vec::new();
+		
+		for key in due_keys {
+			let pending = self.pending.remove(&key)
+				.expect("key was just taken from the map");
+			
+			let is_suppressed = match self.config.suppression {
+				BroadcastSuppression::Counter { threshold } => pending.copies >= threshold,
+				BroadcastSuppression::None | BroadcastSuppression::Probabilistic { .. } => false,
+			};
+			
+			if is_suppressed {
+				debug!(?key, copies = pending.copies, "Suppressed rebroadcast");
+				continue;
+			}
+			
+			frames.push(pending.frame);
+		}
+		
+		frames
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const JITTER: Duration = Duration::from_millis(500);
+	
+	fn key() -> FloodKey {
+		FloodKey::RouteRequest {
+			origin: ATAddress::new(*b"0001").unwrap(),
+			id: 1,
+		}
+	}
+	
+	fn queue(suppression: BroadcastSuppression) -> BroadcastQueue {
+		BroadcastQueue::new(FloodingConfig {
+			max_jitter: JITTER,
+			suppression,
+		})
+	}
+	
+	#[test]
+	fn delays_by_jitter() {
+		let mut queue = queue(BroadcastSuppression::None);
+		let now = Instant::now();
+		
+		assert!(queue.schedule(key(), b"frame"[..].into(), now, &mut rand::thread_rng()));
+		assert!(queue.next_deadline().unwrap() < now + JITTER);
+		
+		assert_eq!(queue.take_due(now + JITTER), vec![Box::from(&b"frame"[..])]);
+		assert!(queue.next_deadline().is_none());
+	}
+	
+	#[test]
+	fn counter_suppresses_after_threshold() {
+		let mut queue = queue(BroadcastSuppression::Counter {
+			threshold: 3,
+		});
+		let now = Instant::now();
+		
+		let other_key = FloodKey::RouteError {
+			destination: ATAddress::new(*b"0002").unwrap(),
+		};
+		
+		queue.schedule(key(), b"frame"[..].into(), now, &mut rand::thread_rng());
+		queue.schedule(other_key, b"other"[..].into(), now, &mut rand::thread_rng());
+		
+		queue.overheard(key());
+		queue.overheard(key());
+		queue.overheard(other_key);
+		
+		assert_eq!(queue.take_due(now + JITTER), vec![Box::from(&b"other"[..])]);
+	}
+	
+	#[test]
+	fn probabilistic_extremes() {
+		let now = Instant::now();
+		
+		let mut never = queue(BroadcastSuppression::Probabilistic {
+			probability: 0.0,
+		});
+		assert!(!never.schedule(key(), b"frame"[..].into(), now, &mut rand::thread_rng()));
+		
+		let mut always = queue(BroadcastSuppression::Probabilistic {
+			probability: 1.0,
+		});
+		assert!(always.schedule(key(), b"frame"[..].into(), now, &mut rand::thread_rng()));
+		assert_eq!(always.take_due(now + JITTER).len(), 1);
+	}
+}
\ No newline at end of file
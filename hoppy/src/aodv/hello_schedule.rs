@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Hellos are sent up to this fraction of the interval early, so nodes don't synchronise their broadcasts
+const HELLO_JITTER: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HelloIntervalConfig {
+	/// Interval used while the neighborhood is changing
+	pub min: Duration,
+	/// The interval doubles for every hello without changes until it reaches this
+	pub max: Duration,
+}
+
+pub struct HelloSchedule {
+	config: HelloIntervalConfig,
+	interval: Duration,
+}
+
+impl HelloSchedule {
+	pub fn new(config: HelloIntervalConfig) -> Self {
+		Self {
+			config,
+			interval: config.min,
+		}
+	}
+	
+	pub fn min_interval(&self) -> Duration {
+		self.config.min
+	}
+	
+	/// Interval until the hello after the next one, which is also advertised in the next one
+	pub fn next_interval(&mut self, neighborhood_changed: bool) -> Duration {
+		self.interval = if neighborhood_changed {
+			self.config.min
+		} else {
+			(self.interval * 2).min(self.config.max)
+		};
+		
+		self.interval
+	}
+}
+
+/// Shortens `interval` by a random amount, never making it longer than advertised
+pub fn with_jitter(interval: Duration, rng: &mut impl Rng) -> Duration {
+	interval.mul_f64(1.0 - rng.gen_range(0.0..HELLO_JITTER))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const CONFIG: HelloIntervalConfig = HelloIntervalConfig {
+		min: Duration::from_secs(10),
+		max: Duration::from_secs(60),
+	};
+	
+	#[test]
+	fn backs_off_while_stable() {
+		let mut schedule = HelloSchedule::new(CONFIG);
+		
+		assert_eq!(schedule.next_interval(true), Duration::from_secs(10));
+		assert_eq!(schedule.next_interval(false), Duration::from_secs(20));
+		assert_eq!(schedule.next_interval(false), Duration::from_secs(40));
+		assert_eq!(schedule.next_interval(false), Duration::from_secs(60));
+		assert_eq!(schedule.next_interval(false), Duration::from_secs(60));
+		
+		assert_eq!(schedule.next_interval(true), Duration::from_secs(10));
+	}
+	
+	#[test]
+	fn jitter_only_shortens() {
+		for _ in 0..100 {
+			let interval = with_jitter(CONFIG.min, &mut rand::thread_rng());
+			
+			assert!(interval <= CONFIG.min);
+			assert!(interval >= CONFIG.min.mul_f64(1.0 - HELLO_JITTER));
+		}
+	}
+}
\ No newline at end of file
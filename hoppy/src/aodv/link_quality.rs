@@ -0,0 +1,114 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+/// Number of hello intervals considered for the hello delivery ratio
+pub const HELLO_HISTORY_LENGTH: u32 = 10;
+
+/// What is known about the link to a neighbor
+#[derive(Debug, Clone, Copy)]
+pub struct LinkQuality {
+	/// Fraction of the neighbor's recent hellos that were received, between 0 and 1
+	pub hello_ratio: f32,
+	/// Smoothed average signal strength of the frames from the neighbor in dBm, if the AT module reports it
+	pub rssi: Option<i16>,
+	/// Smoothed average signal-to-noise ratio of the frames from the neighbor in dB, if the AT module reports it
+	pub snr: Option<i8>,
+}
+
+impl Default for LinkQuality {
+	fn default() -> Self {
+		// nothing is known about the link yet, so assume it is fine
+		Self {
+			hello_ratio: 1.0,
+			rssi: None,
+			snr: None,
+		}
+	}
+}
+
+pub struct HelloHistory {
+	first_hello: Instant,
+	hellos: VecDeque<Instant>,
+}
+
+impl HelloHistory {
+	pub fn new(now: Instant) -> Self {
+		Self {
+			first_hello: now,
+			hellos: VecDeque::from([now]),
+		}
+	}
+	
+	pub fn record(&mut self, now: Instant, hello_interval: Duration) {
+		self.hellos.push_back(now);
+		self.prune(now, hello_interval);
+	}
+	
+	/// Records a frame other than a hello, standing in for a hello the neighbor suppressed
+	pub fn record_activity(&mut self, now: Instant, hello_interval: Duration) {
+		// at most one frame per hello interval counts, so busy neighbors don't look better than they are
+		let is_covered = self.hellos.back()
+			.is_some_and(|&hello| now - hello < hello_interval);
+		
+		if !is_covered {
+			self.record(now, hello_interval);
+		}
+	}
+	
+	fn prune(&mut self, now: Instant, hello_interval: Duration) {
+		let window = hello_interval * HELLO_HISTORY_LENGTH;
+		
+		while self.hellos.front().is_some_and(|&hello| now - hello > window) {
+			self.hellos.pop_front();
+		}
+	}
+	
+	pub fn ratio(&mut self, now: Instant, hello_interval: Duration) -> f32 {
+		self.prune(now, hello_interval);
+		
+		// a neighbor that was heard for the first time recently can't have missed older hellos
+		let observed = (now - self.first_hello).min(hello_interval * HELLO_HISTORY_LENGTH);
+		let expected = (observed.as_secs_f32() / hello_interval.as_secs_f32()).floor().max(1.0);
+		
+		(self.hellos.len() as f32 / expected).min(1.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const INTERVAL: Duration = Duration::from_secs(10);
+	
+	#[test]
+	fn all_hellos_received() {
+		let start = Instant::now();
+		let mut history = HelloHistory::new(start);
+		
+		for i in 1..=20 {
+			history.record(start + INTERVAL * i, INTERVAL);
+		}
+		
+		assert_eq!(history.ratio(start + INTERVAL * 20, INTERVAL), 1.0);
+	}
+	
+	#[test]
+	fn every_other_hello_received() {
+		let start = Instant::now();
+		let mut history = HelloHistory::new(start);
+		
+		for i in 1..=10 {
+			history.record(start + INTERVAL * i * 2, INTERVAL);
+		}
+		
+		assert_eq!(history.ratio(start + INTERVAL * 21, INTERVAL), 0.5);
+	}
+	
+	#[test]
+	fn forgets_old_hellos() {
+		let start = Instant::now();
+		let mut history = HelloHistory::new(start);
+		
+		assert_eq!(history.ratio(start + INTERVAL, INTERVAL), 1.0);
+		assert_eq!(history.ratio(start + INTERVAL * 11, INTERVAL), 0.0);
+	}
+}
\ No newline at end of file
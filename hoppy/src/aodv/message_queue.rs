@@ -0,0 +1,225 @@
+use std::{collections::VecDeque, sync::{Arc, Condvar, Mutex, MutexGuard, Weak}, time::{Duration, Instant}};
+
+use tracing::warn;
+
+use crate::at_module::{at_address::ATAddress, ATSignal};
+
+/// Data addressed to this node
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedMessage {
+	pub origin: ATAddress,
+	pub payload: Box<[u8]>,
+	/// Number of links the data travelled, 1 if the origin is a neighbor
+	pub hop_count: u8,
+	pub received: Instant,
+	/// Signal of the last hop, if the AT module reports it
+	pub signal: Option<ATSignal>,
+}
+
+/// What happens to new values when a queue is full, the event loop never waits for the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+	/// Drop the value that didn't fit, keeping the queued ones
+	DropNewest,
+	/// Drop the oldest queued value to make room
+	DropOldest,
+}
+
+struct QueueState<T> {
+	values: VecDeque<T>,
+	dropped: u64,
+	closed: bool,
+}
+
+struct MessageQueue<T> {
+	state: Mutex<QueueState<T>>,
+	value_added: Condvar,
+	capacity: usize,
+	overflow: Overflow,
+}
+
+impl<T> MessageQueue<T> {
+	fn state_lock(&self) -> MutexGuard<'_, QueueState<T>> {
+		self.state.lock()
+			.expect("no threads should panic")
+	}
+}
+
+pub(super) fn message_queue<T>(capacity: usize, overflow: Overflow) -> (MessageSender<T>, MessageReceiver<T>) {
+	let queue = Arc::new(MessageQueue {
+		state: Mutex::new(QueueState {
+			values: VecDeque::with_capacity(capacity),
+			dropped: 0,
+			closed: false,
+		}),
+		value_added: Condvar::new(),
+		capacity,
+		overflow,
+	});
+	
+	let sender = MessageSender {
+		queue: Arc::downgrade(&queue),
+	};
+	
+	(sender, MessageReceiver { queue })
+}
+
+/// Somewhere the event loop passes values to without waiting
+pub(super) trait Subscriber<T>: Send {
+	/// Returns false once the receiving side is gone
+	fn send(&self, value: T) -> bool;
+}
+
+/// Closes the queue when dropped, so receivers stop waiting
+pub(super) struct MessageSender<T> {
+	queue: Weak<MessageQueue<T>>,
+}
+
+impl<T: Send> Subscriber<T> for MessageSender<T> {
+	fn send(&self, value: T) -> bool {
+		let Some(queue) = self.queue.upgrade() else {
+			return false;
+		};
+		
+		let mut state = queue.state_lock();
+		
+		if state.values.len() >= queue.capacity {
+			state.dropped += 1;
+			
+			match queue.overflow {
+				Overflow::DropNewest => {
+					warn!("Receive queue is full, dropping the newest entry");
+					return true;
+				},
+				Overflow::DropOldest => {
+					warn!("Receive queue is full, dropping the oldest entry");
+					state.values.pop_front();
+				},
+			}
+		}
+		
+		state.values.push_back(value);
+		queue.value_added.notify_one();
+		
+		true
+	}
+}
+
+impl<T> Drop for MessageSender<T> {
+	fn drop(&mut self) {
+		let Some(queue) = self.queue.upgrade() else {
+			return;
+		};
+		
+		queue.state_lock().closed = true;
+		queue.value_added.notify_all();
+	}
+}
+
+/// Bounded queue of received messages or events, consumed at the application's own pace
+pub struct MessageReceiver<T = ReceivedMessage> {
+	queue: Arc<MessageQueue<T>>,
+}
+
+impl<T> MessageReceiver<T> {
+	/// Blocks until a value arrives, returning None once the controller stopped and the queue is empty
+	pub fn recv(&self) -> Option<T> {
+		let mut state = self.queue.value_added.wait_while(self.queue.state_lock(), |state| state.values.is_empty() && !state.closed)
+			.expect("no threads should panic");
+		
+		state.values.pop_front()
+	}
+	
+	/// Like `recv`, but also returns None if no value arrives before the timeout runs out
+	pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+		let (mut state, _) = self.queue.value_added.wait_timeout_while(self.queue.state_lock(), timeout, |state| state.values.is_empty() && !state.closed)
+			.expect("no threads should panic");
+		
+		state.values.pop_front()
+	}
+	
+	pub fn try_recv(&self) -> Option<T> {
+		self.queue.state_lock()
+			.values
+			.pop_front()
+	}
+	
+	/// Number of values dropped because the queue was full
+	pub fn dropped(&self) -> u64 {
+		self.queue.state_lock().dropped
+	}
+	
+	/// Blocking iterator over received values, ending once the controller stopped
+	pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+		std::iter::from_fn(|| self.recv())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+	
+	use super::*;
+	
+	fn message(payload: &[u8]) -> ReceivedMessage {
+		ReceivedMessage {
+			origin: ATAddress::new(*b"0001").unwrap(),
+			payload: payload.into(),
+			hop_count: 1,
+			received: Instant::now(),
+			signal: None,
+		}
+	}
+	
+	#[test]
+	fn drops_newest_when_full() {
+		let (sender, receiver) = message_queue(2, Overflow::DropNewest);
+		
+		for payload in [b"a", b"b", b"c"] {
+			assert!(sender.send(message(payload)));
+		}
+		
+		assert_eq!(&*receiver.try_recv().unwrap().payload, b"a");
+		assert_eq!(&*receiver.try_recv().unwrap().payload, b"b");
+		assert_eq!(receiver.try_recv(), None);
+		assert_eq!(receiver.dropped(), 1);
+	}
+	
+	#[test]
+	fn drops_oldest_when_full() {
+		let (sender, receiver) = message_queue(2, Overflow::DropOldest);
+		
+		for payload in [b"a", b"b", b"c"] {
+			sender.send(message(payload));
+		}
+		
+		assert_eq!(&*receiver.try_recv().unwrap().payload, b"b");
+		assert_eq!(&*receiver.try_recv().unwrap().payload, b"c");
+		assert_eq!(receiver.dropped(), 1);
+	}
+	
+	#[test]
+	fn iterator_ends_when_sender_is_dropped() {
+		let (sender, receiver) = message_queue(8, Overflow::DropNewest);
+		
+		thread::spawn(move || {
+			sender.send(message(b"a"));
+			sender.send(message(b"b"));
+		});
+		
+		let payloads: Vec<_> = receiver.iter()
+			.map(|message| message.payload)
+			.collect();
+		
+		assert_eq!(payloads, [b"a"[..].into(), b"b"[..].into()]);
+	}
+	
+	#[test]
+	fn sender_notices_dropped_receiver() {
+		let (sender, receiver) = message_queue(8, Overflow::DropNewest);
+		
+		drop(receiver);
+		
+		assert!(!sender.send(message(b"a")));
+	}
+}
\ No newline at end of file
@@ -0,0 +1,92 @@
+use super::link_quality::LinkQuality;
+
+/// Cost of a perfect link, metrics scale their costs relative to it
+pub const LINK_COST_UNIT: u16 = 10;
+
+/// Decides how costly a route is, routes with lower accumulated costs are preferred
+pub trait RouteMetric: Send + Sync {
+	/// Cost of a single link, added up along the route
+	fn link_cost(&self, link: &LinkQuality) -> u16;
+}
+
+/// Every link costs the same, which makes the route cost proportional to the hop count
+pub struct HopCount;
+
+impl RouteMetric for HopCount {
+	fn link_cost(&self, _link: &LinkQuality) -> u16 {
+		LINK_COST_UNIT
+	}
+}
+
+/// Expected transmission count, estimated from the hello delivery ratio assuming a symmetric link
+pub struct Etx;
+
+impl Etx {
+	/// Keeps the cost of links that lost all recent hellos finite
+	const MIN_RATIO: f32 = 0.1;
+}
+
+impl RouteMetric for Etx {
+	fn link_cost(&self, link: &LinkQuality) -> u16 {
+		let ratio = link.hello_ratio.max(Self::MIN_RATIO);
+		let etx = 1.0 / (ratio * ratio);
+		
+		(etx * LINK_COST_UNIT as f32).round() as u16
+	}
+}
+
+/// Adds a penalty for weak signals, behaves like `HopCount` if no signal information is available
+pub struct SignalStrength;
+
+impl SignalStrength {
+	/// SNR in dB above which a LoRa link is considered reliable
+	const GOOD_SNR: i8 = 5;
+	/// RSSI in dBm above which a link is considered reliable, used if the SNR is unknown
+	const GOOD_RSSI: i16 = -90;
+}
+
+impl RouteMetric for SignalStrength {
+	fn link_cost(&self, link: &LinkQuality) -> u16 {
+		let penalty = match (link.snr, link.rssi) {
+			(Some(snr), _) => (Self::GOOD_SNR as i16 - snr as i16).max(0) * 2,
+			(None, Some(rssi)) => Self::GOOD_RSSI.saturating_sub(rssi).max(0) / 2,
+			(None, None) => 0,
+		};
+		
+		LINK_COST_UNIT + penalty as u16
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn link(rssi: Option<i16>, snr: Option<i8>) -> LinkQuality {
+		LinkQuality {
+			rssi,
+			snr,
+			..LinkQuality::default()
+		}
+	}
+	
+	#[test]
+	fn penalizes_weak_signals() {
+		assert_eq!(SignalStrength.link_cost(&link(None, None)), LINK_COST_UNIT);
+		assert_eq!(SignalStrength.link_cost(&link(Some(-80), Some(10))), LINK_COST_UNIT);
+		assert_eq!(SignalStrength.link_cost(&link(Some(-80), Some(0))), LINK_COST_UNIT + 10);
+		assert_eq!(SignalStrength.link_cost(&link(Some(-110), None)), LINK_COST_UNIT + 10);
+	}
+	
+	#[test]
+	fn handles_extreme_signals() {
+		for rssi in [i16::MIN, i16::MAX] {
+			SignalStrength.link_cost(&link(Some(rssi), None));
+		}
+		
+		for snr in [i8::MIN, i8::MAX] {
+			SignalStrength.link_cost(&link(None, Some(snr)));
+		}
+		
+		assert_eq!(SignalStrength.link_cost(&link(Some(i16::MAX), None)), LINK_COST_UNIT);
+	}
+}
\ No newline at end of file
@@ -0,0 +1,46 @@
+use std::collections::BTreeSet;
+
+use crate::at_module::at_address::ATAddress;
+
+/// Which senders' frames are handled, for emulating a topology on a bench or shutting out misbehaving nodes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NeighborFilter {
+	#[default]
+	AllowAll,
+	/// Frames from these senders are ignored
+	Deny(BTreeSet<ATAddress>),
+	/// Only frames from these senders are handled
+	Allow(BTreeSet<ATAddress>),
+}
+
+impl NeighborFilter {
+	pub fn allows(&self, sender: ATAddress) -> bool {
+		match self {
+			NeighborFilter::AllowAll => true,
+			NeighborFilter::Deny(denied) => !denied.contains(&sender),
+			NeighborFilter::Allow(allowed) => allowed.contains(&sender),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn address(address: &[u8; 4]) -> ATAddress {
+		ATAddress::new(*address).unwrap()
+	}
+	
+	#[test]
+	fn filters_senders() {
+		let listed = BTreeSet::from([address(b"0001")]);
+		
+		assert!(NeighborFilter::AllowAll.allows(address(b"0001")));
+		
+		assert!(!NeighborFilter::Deny(listed.clone()).allows(address(b"0001")));
+		assert!(NeighborFilter::Deny(listed.clone()).allows(address(b"0002")));
+		
+		assert!(NeighborFilter::Allow(listed.clone()).allows(address(b"0001")));
+		assert!(!NeighborFilter::Allow(listed).allows(address(b"0002")));
+	}
+}
\ No newline at end of file
@@ -0,0 +1,451 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display, time::{Duration, Instant}};
+
+use crate::at_module::{at_address::ATAddress, ATSignal};
+
+use super::{link_quality::{HelloHistory, LinkQuality, HELLO_HISTORY_LENGTH}, node_info::NodeInfo, packets::HelloPacket};
+
+/// Weight of a new sample in the rolling signal averages
+const SIGNAL_SMOOTHING: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+	/// The neighbor proved to be alive within the hello timeout
+	Up,
+	/// The neighbor timed out, the link broke or it was never confirmed to be alive
+	Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborEvent {
+	Up(ATAddress),
+	Down(ATAddress),
+}
+
+/// Link statistics of a neighbor
+#[derive(Debug, Clone)]
+pub struct NeighborStats {
+	pub address: ATAddress,
+	pub state: LinkState,
+	pub link_quality: LinkQuality,
+	/// When the last frame was received from this neighbor
+	pub last_heard: Instant,
+	/// Metadata from the neighbor's last hello
+	pub info: NodeInfo,
+	/// Neighbors of the neighbor according to its last hello
+	pub neighbors: Vec<ATAddress>,
+}
+
+/// What the routing table needs to know about a link that is up
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+	/// Sequence number of the neighbor's last hello
+	pub sequence: u16,
+	/// Cost of the link according to the route metric when the last hello was received
+	pub link_cost: u16,
+	pub last_heard: Instant,
+}
+
+struct Neighbor {
+	state: LinkState,
+	/// The link goes down at this point unless the neighbor proves to be alive again
+	expires: Instant,
+	sequence: Option<u16>,
+	link_cost: u16,
+	last_heard: Instant,
+	/// Interval the neighbor advertised in its last hello
+	hello_interval: Duration,
+	hellos: Option<HelloHistory>,
+	rssi: Option<f32>,
+	snr: Option<f32>,
+	info: NodeInfo,
+	neighbors: Vec<ATAddress>,
+}
+
+fn rolling_average(average: Option<f32>, sample: f32) -> f32 {
+	match average {
+		Some(average) => average + SIGNAL_SMOOTHING * (sample - average),
+		None => sample,
+	}
+}
+
+impl Neighbor {
+	fn new(now: Instant, hello_interval: Duration) -> Self {
+		Self {
+			state: LinkState::Down,
+			expires: now,
+			sequence: None,
+			link_cost: 0,
+			last_heard: now,
+			hello_interval,
+			hellos: None,
+			rssi: None,
+			snr: None,
+			info: NodeInfo::default(),
+			neighbors: Vec::new(),
+		}
+	}
+	
+	fn link_quality(&mut self, now: Instant) -> LinkQuality {
+		let hello_interval = self.hello_interval;
+		
+		LinkQuality {
+			hello_ratio: self.hellos.as_mut()
+				.map(|hellos| hellos.ratio(now, hello_interval))
+				.unwrap_or(1.0),
+			rssi: self.rssi.map(|rssi| rssi.round() as i16),
+			snr: self.snr.map(|snr| snr.round() as i8),
+		}
+	}
+}
+
+pub struct NeighborTable {
+	neighbors: BTreeMap<ATAddress, Neighbor>,
+	/// Assumed for neighbors that didn't advertise an interval yet
+	hello_interval: Duration,
+	/// Timeout for neighbors using `hello_interval`, scaled for neighbors advertising other intervals
+	hello_timeout: Duration,
+}
+
+impl NeighborTable {
+	pub fn new(hello_interval: Duration, hello_timeout: Duration) -> Self {
+		Self {
+			neighbors: BTreeMap::new(),
+			hello_interval,
+			hello_timeout,
+		}
+	}
+	
+	fn neighbor(&mut self, address: ATAddress, now: Instant) -> &mut Neighbor {
+		let hello_interval = self.hello_interval;
+		
+		self.neighbors.entry(address)
+			.or_insert_with(|| Neighbor::new(now, hello_interval))
+	}
+	
+	/// Records any frame received from `address`, every frame proves the neighbor is alive
+	///
+	/// Hellos are counted in the hello history by `record_hello` instead.
+	pub fn record_frame(&mut self, address: ATAddress, signal: Option<ATSignal>, is_hello: bool, now: Instant) -> Option<NeighborEvent> {
+		let neighbor = self.neighbor(address, now);
+		let hello_interval = neighbor.hello_interval;
+		
+		neighbor.last_heard = now;
+		
+		if let Some(ATSignal { rssi, snr }) = signal {
+			neighbor.rssi = Some(rolling_average(neighbor.rssi, rssi as f32));
+			neighbor.snr = Some(rolling_average(neighbor.snr, snr as f32));
+		}
+		
+		// neighbors skip hellos while they are transmitting anyway
+		if let Some(hellos) = neighbor.hellos.as_mut().filter(|_| !is_hello) {
+			hellos.record_activity(now, hello_interval);
+		}
+		
+		self.refresh(address, now)
+	}
+	
+	pub fn record_hello(&mut self, address: ATAddress, hello: &HelloPacket, link_cost: u16, now: Instant) -> Option<NeighborEvent> {
+		let default_interval = self.hello_interval;
+		let neighbor = self.neighbor(address, now);
+		
+		// an interval of 0 would time the neighbor out immediately
+		let hello_interval = if hello.interval.is_zero() {
+			default_interval
+		} else {
+			hello.interval
+		};
+		
+		neighbor.hello_interval = hello_interval;
+		neighbor.sequence = Some(hello.sequence);
+		neighbor.link_cost = link_cost;
+		neighbor.info = hello.info.clone();
+		neighbor.neighbors = hello.neighbors.clone();
+		
+		match &mut neighbor.hellos {
+			Some(hellos) => hellos.record(now, hello_interval),
+			None => neighbor.hellos = Some(HelloHistory::new(now)),
+		}
+		
+		self.refresh(address, now)
+	}
+	
+	/// Restarts the liveness timer of a neighbor that proved to be alive
+	fn refresh(&mut self, address: ATAddress, now: Instant) -> Option<NeighborEvent> {
+		let hello_timeout = self.hello_timeout.as_secs_f64() / self.hello_interval.as_secs_f64();
+		let neighbor = self.neighbor(address, now);
+		
+		// neighbors that send hellos less often get more time to send the next one
+		neighbor.expires = now + neighbor.hello_interval.mul_f64(hello_timeout);
+		
+		if neighbor.state == LinkState::Up {
+			return None;
+		}
+		
+		neighbor.state = LinkState::Up;
+		Some(NeighborEvent::Up(address))
+	}
+	
+	pub fn mark_down(&mut self, address: ATAddress) -> Option<NeighborEvent> {
+		let neighbor = self.neighbors.get_mut(&address)?;
+		
+		if neighbor.state == LinkState::Down {
+			return None;
+		}
+		
+		neighbor.state = LinkState::Down;
+		Some(NeighborEvent::Down(address))
+	}
+	
+	/// Returns the link to `address` if it is up and the neighbor sent a hello
+	pub fn link(&self, address: ATAddress) -> Option<Link> {
+		let neighbor = self.neighbors.get(&address)?;
+		
+		if neighbor.state != LinkState::Up {
+			return None;
+		}
+		
+		Some(Link {
+			sequence: neighbor.sequence?,
+			link_cost: neighbor.link_cost,
+			last_heard: neighbor.last_heard,
+		})
+	}
+	
+	pub fn up_neighbors(&self) -> Vec<ATAddress> {
+		self.neighbors.iter()
+			.filter(|(_, neighbor)| neighbor.state == LinkState::Up)
+			.map(|(&address, _)| address)
+			.collect()
+	}
+	
+	/// Nodes two hops away, each with the neighbors that have a link to it
+	pub fn two_hop_neighbors(&self, own_address: ATAddress) -> BTreeMap<ATAddress, BTreeSet<ATAddress>> {
+		let mut two_hop_neighbors: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+		
+		for (&address, neighbor) in &self.neighbors {
+			if neighbor.state != LinkState::Up {
+				continue;
+			}
+			
+			for &two_hop_neighbor in &neighbor.neighbors {
+				if two_hop_neighbor == own_address || self.link(two_hop_neighbor).is_some() {
+					continue;
+				}
+				
+				two_hop_neighbors.entry(two_hop_neighbor)
+					.or_default()
+					.insert(address);
+			}
+		}
+		
+		two_hop_neighbors
+	}
+	
+	/// Neighbors that are up but whose liveness timer ran out
+	pub fn expired(&self, now: Instant) -> Vec<ATAddress> {
+		self.neighbors.iter()
+			.filter(|(_, neighbor)| neighbor.state == LinkState::Up && now >= neighbor.expires)
+			.map(|(&address, _)| address)
+			.collect()
+	}
+	
+	/// Nothing known about a link is treated like a perfect link
+	pub fn link_quality(&mut self, address: ATAddress, now: Instant) -> LinkQuality {
+		self.neighbors.get_mut(&address)
+			.map(|neighbor| neighbor.link_quality(now))
+			.unwrap_or_default()
+	}
+	
+	/// Forgets neighbors that are down and weren't heard from for as long as hellos are remembered
+	pub fn remove_stale(&mut self, now: Instant) {
+		self.neighbors.retain(|_, neighbor| {
+			neighbor.state == LinkState::Up || now - neighbor.last_heard <= neighbor.hello_interval * HELLO_HISTORY_LENGTH
+		});
+	}
+	
+	pub fn stats(&mut self, now: Instant) -> Vec<NeighborStats> {
+		self.neighbors.iter_mut()
+			.map(|(&address, neighbor)| NeighborStats {
+				address,
+				state: neighbor.state,
+				link_quality: neighbor.link_quality(now),
+				last_heard: neighbor.last_heard,
+				info: neighbor.info.clone(),
+				neighbors: neighbor.neighbors.clone(),
+			})
+			.collect()
+	}
+}
+
+impl Display for NeighborTable {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "+----+----+----+----+")?;
+		writeln!(f, "|ADDR|STAT|NSEQ|COST|")?;
+		writeln!(f, "+----+----+----+----+")?;
+		
+		for (address, neighbor) in &self.neighbors {
+			let state = match neighbor.state {
+				LinkState::Up => "  Up",
+				LinkState::Down => "Down",
+			};
+			
+			match neighbor.sequence {
+				Some(sequence) => writeln!(f, "|{address}|{state}|{sequence:04X}|{:04X}|", neighbor.link_cost)?,
+				None => writeln!(f, "|{address}|{state}|None|None|")?,
+			}
+		}
+		
+		write!(f, "+----+----+----+----+")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const INTERVAL: Duration = Duration::from_secs(10);
+	const TIMEOUT: Duration = Duration::from_secs(25);
+	
+	fn address() -> ATAddress {
+		ATAddress::new(*b"0001").unwrap()
+	}
+	
+	fn hello(sequence: u16, neighbors: &[&[u8; 4]]) -> HelloPacket {
+		HelloPacket {
+			sequence,
+			interval: INTERVAL,
+			info: NodeInfo::default(),
+			neighbors: neighbors.iter()
+				.map(|&&neighbor| ATAddress::new(neighbor).unwrap())
+				.collect(),
+		}
+	}
+	
+	#[test]
+	fn averages_signal() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let now = Instant::now();
+		
+		neighbor_table.record_frame(address(), Some(ATSignal { rssi: -80, snr: 8 }), false, now);
+		neighbor_table.record_frame(address(), Some(ATSignal { rssi: -100, snr: 0 }), false, now);
+		// frames without signal information don't change the averages
+		neighbor_table.record_frame(address(), None, false, now);
+		
+		let link_quality = neighbor_table.link_quality(address(), now);
+		
+		assert_eq!(link_quality.rssi, Some(-85));
+		assert_eq!(link_quality.snr, Some(6));
+	}
+	
+	#[test]
+	fn link_goes_up_and_down() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let now = Instant::now();
+		
+		assert_eq!(neighbor_table.record_hello(address(), &hello(1, &[]), 10, now), Some(NeighborEvent::Up(address())));
+		assert_eq!(neighbor_table.record_hello(address(), &hello(2, &[]), 10, now + INTERVAL), None);
+		assert_eq!(neighbor_table.link(address()).unwrap().sequence, 2);
+		
+		assert!(neighbor_table.expired(now + INTERVAL + TIMEOUT / 2).is_empty());
+		assert_eq!(neighbor_table.expired(now + INTERVAL + TIMEOUT), vec![address()]);
+		
+		assert_eq!(neighbor_table.mark_down(address()), Some(NeighborEvent::Down(address())));
+		assert_eq!(neighbor_table.mark_down(address()), None);
+		assert!(neighbor_table.link(address()).is_none());
+	}
+	
+	#[test]
+	fn any_frame_proves_liveness() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let now = Instant::now();
+		
+		// a node that was never heard from before comes up without a hello
+		assert_eq!(neighbor_table.record_frame(address(), None, false, now), Some(NeighborEvent::Up(address())));
+		// no hello, so no sequence number to build a route from
+		assert!(neighbor_table.link(address()).is_none());
+		
+		neighbor_table.record_hello(address(), &hello(1, &[]), 10, now);
+		
+		// the neighbor only forwards data instead of sending hellos
+		for i in 1..=5 {
+			neighbor_table.record_frame(address(), None, false, now + TIMEOUT / 2 * i);
+		}
+		
+		let last_frame = now + TIMEOUT / 2 * 5;
+		
+		assert!(neighbor_table.expired(last_frame + TIMEOUT / 2).is_empty());
+		assert_eq!(neighbor_table.link_quality(address(), last_frame).hello_ratio, 1.0);
+	}
+	
+	#[test]
+	fn forgets_silent_neighbors() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let now = Instant::now();
+		
+		neighbor_table.record_hello(address(), &hello(1, &[]), 10, now);
+		
+		// neighbors that are up are kept until they time out
+		neighbor_table.remove_stale(now + INTERVAL * 11);
+		assert_eq!(neighbor_table.stats(now).len(), 1);
+		
+		neighbor_table.mark_down(address());
+		neighbor_table.remove_stale(now + INTERVAL * 11);
+		assert!(neighbor_table.stats(now).is_empty());
+	}
+	
+	#[test]
+	fn builds_two_hop_view() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let own_address = ATAddress::new(*b"0000").unwrap();
+		let other = ATAddress::new(*b"0002").unwrap();
+		let now = Instant::now();
+		
+		neighbor_table.record_hello(address(), &hello(1, &[b"0000", b"0002", b"0003"]), 10, now);
+		neighbor_table.record_hello(other, &hello(1, &[b"0000", b"0001", b"0003", b"0004"]), 10, now);
+		
+		let two_hop_neighbors = neighbor_table.two_hop_neighbors(own_address);
+		
+		// direct neighbors and this node itself are not two hops away
+		assert_eq!(two_hop_neighbors.keys().collect::<Vec<_>>(), [&ATAddress::new(*b"0003").unwrap(), &ATAddress::new(*b"0004").unwrap()]);
+		assert_eq!(two_hop_neighbors[&ATAddress::new(*b"0003").unwrap()], BTreeSet::from([address(), other]));
+		
+		neighbor_table.mark_down(other);
+		
+		// the former neighbor can still be reached over the remaining one
+		let two_hop_neighbors = neighbor_table.two_hop_neighbors(own_address);
+		assert_eq!(two_hop_neighbors.keys().collect::<Vec<_>>(), [&other, &ATAddress::new(*b"0003").unwrap()]);
+	}
+	
+	#[test]
+	fn lost_hellos_lower_the_ratio() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let now = Instant::now();
+		
+		// every other hello is lost, the received ones are recorded as frames first like the protocol does
+		for i in 0..=10 {
+			let received = now + INTERVAL * i * 2;
+			
+			neighbor_table.record_frame(address(), None, true, received);
+			neighbor_table.record_hello(address(), &hello(i as u16, &[]), 10, received);
+		}
+		
+		let hello_ratio = neighbor_table.link_quality(address(), now + INTERVAL * 21).hello_ratio;
+		assert!((hello_ratio - 0.5).abs() < 0.1, "hello ratio {hello_ratio}");
+	}
+	
+	#[test]
+	fn timeout_scales_with_advertised_interval() {
+		let mut neighbor_table = NeighborTable::new(INTERVAL, TIMEOUT);
+		let now = Instant::now();
+		
+		let hello = HelloPacket {
+			interval: INTERVAL * 4,
+			..hello(1, &[])
+		};
+		
+		neighbor_table.record_hello(address(), &hello, 10, now);
+		
+		assert!(neighbor_table.expired(now + TIMEOUT).is_empty());
+		assert_eq!(neighbor_table.expired(now + TIMEOUT * 4), vec![address()]);
+	}
+}
\ No newline at end of file
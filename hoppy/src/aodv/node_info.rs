@@ -0,0 +1,74 @@
+/// What a node can do, advertised to its neighbors in hellos
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+	/// Connects the mesh to another network
+	pub gateway: bool,
+	/// Sleeps most of the time, so it should not be relied on for forwarding
+	pub sleepy: bool,
+	/// Forwards packets for other nodes
+	pub relay: bool,
+}
+
+impl Capabilities {
+	const GATEWAY: u8 = 0b001;
+	const SLEEPY: u8 = 0b010;
+	const RELAY: u8 = 0b100;
+	
+	pub(super) fn to_bits(self) -> u8 {
+		let mut bits = 0;
+		
+		if self.gateway {
+			bits |= Self::GATEWAY;
+		}
+		
+		if self.sleepy {
+			bits |= Self::SLEEPY;
+		}
+		
+		if self.relay {
+			bits |= Self::RELAY;
+		}
+		
+		bits
+	}
+	
+	/// Unknown capabilities of newer nodes are ignored
+	pub(super) fn from_bits(bits: u8) -> Self {
+		Self {
+			gateway: bits & Self::GATEWAY != 0,
+			sleepy: bits & Self::SLEEPY != 0,
+			relay: bits & Self::RELAY != 0,
+		}
+	}
+}
+
+/// Metadata a node advertises about itself in hellos
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeInfo {
+	/// Human readable name, truncated to `MAX_NAME_LENGTH` bytes when sent
+	pub name: Option<String>,
+	pub capabilities: Capabilities,
+	/// Remaining battery in percent, `None` for nodes without a battery
+	pub battery: Option<u8>,
+}
+
+/// Longest name in bytes that fits into a hello
+pub const MAX_NAME_LENGTH: usize = 32;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn capabilities_round_trip() {
+		let capabilities = Capabilities {
+			gateway: true,
+			sleepy: false,
+			relay: true,
+		};
+		
+		assert_eq!(capabilities.to_bits(), 0b101);
+		assert_eq!(Capabilities::from_bits(capabilities.to_bits()), capabilities);
+		assert_eq!(Capabilities::from_bits(0xF8), Capabilities::default());
+	}
+}
\ No newline at end of file
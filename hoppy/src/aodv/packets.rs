@@ -1,12 +1,16 @@
-use std::io::{self, ErrorKind};
+use std::{io::{self, ErrorKind}, time::Duration};
 use std::fmt::Debug;
 
-use crate::at_module::at_address::ATAddressError;
-use crate::{at_module::{ATMessage, at_address::ATAddress}, hex::{parse_ascii_hex, Integer, encode_ascii_hex}};
+use crate::{at_module::{ATMessage, ATSignal, at_address::ATAddress}, hex::{parse_ascii_hex, Integer, encode_ascii_hex}};
+
+use super::node_info::{Capabilities, NodeInfo, MAX_NAME_LENGTH};
 
 #[derive(Debug)]
 pub struct AODVPacket {
 	pub sender: ATAddress,
+	/// Set if the sender requested a link-layer acknowledgement for this frame
+	pub link_id: Option<u16>,
+	pub signal: Option<ATSignal>,
 	pub body: AODVPacketBody,
 }
 
@@ -15,6 +19,9 @@ pub enum AODVPacketBody {
 	RouteReply(RouteReplyPacket),
 	RouteError(RouteErrorPacket),
 	Data(DataPacket),
+	LinkAck(LinkAckPacket),
+	DataAck(DataAckPacket),
+	Hello(HelloPacket),
 }
 
 impl Debug for AODVPacketBody {
@@ -26,6 +33,62 @@ impl Debug for AODVPacketBody {
 			RouteReply(packet) => packet.fmt(f),
 			RouteError(packet) => packet.fmt(f),
 			Data(packet) => packet.fmt(f),
+			LinkAck(packet) => packet.fmt(f),
+			DataAck(packet) => packet.fmt(f),
+			Hello(packet) => packet.fmt(f),
+		}
+	}
+}
+
+/// Kind of an AODV packet, as counted in `AODVStats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PacketType {
+	RouteRequest,
+	RouteReply,
+	RouteError,
+	Data,
+	LinkAck,
+	DataAck,
+	Hello,
+}
+
+impl PacketType {
+	/// Type of an encoded frame, skipping a link header
+	pub fn of_frame(frame: &[u8]) -> Option<Self> {
+		use PacketType::*;
+		
+		let tag = match frame.first()? {
+			b'A' => frame.get(5)?,
+			tag => tag,
+		};
+		
+		let packet_type = match tag {
+			b'0' => RouteRequest,
+			b'1' => RouteReply,
+			b'2' => RouteError,
+			b'3' => Data,
+			b'4' => LinkAck,
+			b'5' => DataAck,
+			b'6' => Hello,
+			_ => return None,
+		};
+		
+		Some(packet_type)
+	}
+}
+
+impl AODVPacketBody {
+	pub fn packet_type(&self) -> PacketType {
+		use AODVPacketBody::*;
+		
+		match self {
+			RouteRequest(_) => PacketType::RouteRequest,
+			RouteReply(_) => PacketType::RouteReply,
+			RouteError(_) => PacketType::RouteError,
+			Data(_) => PacketType::Data,
+			LinkAck(_) => PacketType::LinkAck,
+			DataAck(_) => PacketType::DataAck,
+			Hello(_) => PacketType::Hello,
 		}
 	}
 }
@@ -58,28 +121,57 @@ pub fn parse_packet(message: &ATMessage) -> Result<AODVPacket, io::Error> {
 	
 	let mut data: &[u8] = &message.data;
 	
-	let body = match take_bytes(&mut data, 1)?[0] {
+	let mut packet_type = take_bytes(&mut data, 1)?[0];
+	
+	let link_id = if packet_type == b'A' {
+		let link_id = take_int(&mut data, 4)?;
+		packet_type = take_bytes(&mut data, 1)?[0];
+		Some(link_id)
+	} else {
+		None
+	};
+	
+	let body = match packet_type {
 		b'0' => RouteRequest(RouteRequestPacket::parse_from(data)?),
 		b'1' => RouteReply(RouteReplyPacket::parse_from(data)?),
 		b'2' => RouteError(RouteErrorPacket::parse_from(data)?),
 		b'3' => Data(DataPacket::parse_from(data)?),
+		b'4' => LinkAck(LinkAckPacket::parse_from(data)?),
+		b'5' => DataAck(DataAckPacket::parse_from(data)?),
+		b'6' => Hello(HelloPacket::parse_from(data)?),
 		_ => return Err(ErrorKind::InvalidData.into()),
 	};
 	
 	Ok(AODVPacket {
 		sender: message.address,
+		link_id,
+		signal: message.signal,
 		body,
 	})
 }
 
+/// Prefixes an encoded packet with a header requesting a link-layer acknowledgement from the next hop
+pub fn with_link_header(link_id: u16, packet: &[u8]) -> Box<[u8]> {
+	let mut data = Vec::with_capacity(5 + packet.len());
+	data.push(b'A');
+	data.extend(encode_ascii_hex(link_id));
+	data.extend_from_slice(packet);
+	
+	data.into()
+}
+
 #[derive(Debug)]
 pub struct RouteRequestPacket {
 	pub hop_count: u8,
+	/// Accumulated link costs from the origin
+	pub metric: u16,
 	pub id: u16,
 	pub destination: ATAddress,
 	pub destination_sequence: Option<u16>,
 	pub origin: ATAddress,
 	pub origin_sequence: u16,
+	/// Neighbor of the origin that first forwarded the request
+	pub first_hop: ATAddress,
 }
 
 impl RouteRequestPacket {
@@ -92,6 +184,7 @@ impl RouteRequestPacket {
 		
 		Ok(Self {
 			hop_count: take_int(&mut data, 2)?,
+			metric: take_int(&mut data, 4)?,
 			id: take_int(&mut data, 4)?,
 			destination: take_address(&mut data)?,
 			destination_sequence: {
@@ -106,11 +199,12 @@ impl RouteRequestPacket {
 			},
 			origin: take_address(&mut data)?,
 			origin_sequence: take_int(&mut data, 4)?,
+			first_hop: take_address(&mut data)?,
 		})
 	}
 	
 	pub fn to_bytes(&self) -> Box<[u8]> {
-		let mut data = Vec::with_capacity(24);
+		let mut data = Vec::with_capacity(32);
 		data.push(b'0');
 		data.push(if self.destination_sequence.is_none() {
 			b'Y'
@@ -118,11 +212,13 @@ impl RouteRequestPacket {
 			b'N'
 		});
 		data.extend(encode_ascii_hex(self.hop_count));
+		data.extend(encode_ascii_hex(self.metric));
 		data.extend(encode_ascii_hex(self.id));
 		data.extend_from_slice(self.destination.as_bytes());
 		data.extend(encode_ascii_hex(self.destination_sequence.unwrap_or_default()));
 		data.extend_from_slice(self.origin.as_bytes());
 		data.extend(encode_ascii_hex(self.origin_sequence));
+		data.extend_from_slice(self.first_hop.as_bytes());
 		
 		data.into()
 	}
@@ -131,43 +227,44 @@ impl RouteRequestPacket {
 #[derive(Debug)]
 pub struct RouteReplyPacket {
 	pub hop_count: u8,
+	/// Accumulated link costs from the destination
+	pub metric: u16,
 	pub request_destination: ATAddress,
 	pub request_destination_sequence: u16,
-	pub request_origin: Option<ATAddress>,
+	pub request_origin: ATAddress,
+	/// Neighbor of the destination on the path of the reply, if known, so the origin can tell link-disjoint routes apart
+	pub last_hop: Option<ATAddress>,
 }
 
 impl RouteReplyPacket {
 	fn parse_from(mut data: &[u8]) -> Result<Self, io::Error> {
 		Ok(Self {
 			hop_count: take_int(&mut data, 2)?,
+			metric: take_int(&mut data, 4)?,
 			request_destination: take_address(&mut data)?,
 			request_destination_sequence: take_int(&mut data, 4)?,
-			request_origin: {
-				let bytes = take_bytes(&mut data, 4)?;
-				let bytes = bytes.try_into()
-					.expect("take_bytes(_, 4) should always return 4 bytes");
-				
-				match ATAddress::new(bytes) {
-					Ok(address) => Some(address),
-					Err(ATAddressError::BroadcastAddress) => None, // broadcast is used for hello packages which have no request_origin
-					Err(err) => Err(err)?,
-				}
+			request_origin: take_address(&mut data)?,
+			// only appended if known
+			last_hop: if data.is_empty() {
+				None
+			} else {
+				Some(take_address(&mut data)?)
 			},
 		})
 	}
 	
 	pub fn to_bytes(&self) -> Box<[u8]> {
-		let mut data = Vec::with_capacity(15);
+		let mut data = Vec::with_capacity(23);
 		data.push(b'1');
 		data.extend(encode_ascii_hex(self.hop_count));
+		data.extend(encode_ascii_hex(self.metric));
 		data.extend_from_slice(self.request_destination.as_bytes());
 		data.extend(encode_ascii_hex(self.request_destination_sequence));
-		data.extend_from_slice(
-			self.request_origin
-				.as_ref()
-				.map(|address| address.as_bytes())
-				.unwrap_or(b"FFFF") // broadcast is used for hello packages which have no request_origin
-		);
+		data.extend_from_slice(self.request_origin.as_bytes());
+		
+		if let Some(last_hop) = self.last_hop {
+			data.extend_from_slice(last_hop.as_bytes());
+		}
 		
 		data.into()
 	}
@@ -196,6 +293,10 @@ impl RouteErrorPacket {
 
 #[derive(Debug)]
 pub struct DataPacket {
+	pub id: u16,
+	pub ack_requested: bool,
+	/// Number of nodes that forwarded the packet so far
+	pub hop_count: u8,
 	pub destination: ATAddress,
 	pub origin: ATAddress,
 	pub payload: Box<[u8]>,
@@ -203,7 +304,16 @@ pub struct DataPacket {
 
 impl DataPacket {
 	fn parse_from(mut data: &[u8]) -> Result<Self, io::Error> {
+		let ack_requested = match take_bytes(&mut data, 1)? {
+			b"Y" => true,
+			b"N" => false,
+			_ => return Err(ErrorKind::InvalidData.into()),
+		};
+		
 		Ok(Self {
+			id: take_int(&mut data, 4)?,
+			ack_requested,
+			hop_count: take_int(&mut data, 2)?,
 			destination: take_address(&mut data)?,
 			origin: take_address(&mut data)?,
 			payload: data.into(),
@@ -211,12 +321,342 @@ impl DataPacket {
 	}
 	
 	pub fn to_bytes(&self) -> Box<[u8]> {
-		let mut data = Vec::with_capacity(9 + self.payload.len());
+		let mut data = Vec::with_capacity(16 + self.payload.len());
 		data.push(b'3');
+		data.push(if self.ack_requested {
+			b'Y'
+		} else {
+			b'N'
+		});
+		data.extend(encode_ascii_hex(self.id));
+		data.extend(encode_ascii_hex(self.hop_count));
 		data.extend_from_slice(self.destination.as_bytes());
 		data.extend_from_slice(self.origin.as_bytes());
 		data.extend_from_slice(&self.payload);
 		
 		data.into()
 	}
+}
+
+#[derive(Debug)]
+pub struct LinkAckPacket {
+	pub link_id: u16,
+}
+
+impl LinkAckPacket {
+	fn parse_from(mut data: &[u8]) -> Result<Self, io::Error> {
+		Ok(Self {
+			link_id: take_int(&mut data, 4)?,
+		})
+	}
+	
+	pub fn to_bytes(&self) -> Box<[u8]> {
+		let mut data = Vec::with_capacity(5);
+		data.push(b'4');
+		data.extend(encode_ascii_hex(self.link_id));
+		
+		data.into()
+	}
+}
+
+/// End-to-end acknowledgement, sent back to the origin of a DataPacket
+#[derive(Debug)]
+pub struct DataAckPacket {
+	pub id: u16,
+	pub destination: ATAddress,
+	pub origin: ATAddress,
+}
+
+impl DataAckPacket {
+	fn parse_from(mut data: &[u8]) -> Result<Self, io::Error> {
+		Ok(Self {
+			id: take_int(&mut data, 4)?,
+			destination: take_address(&mut data)?,
+			origin: take_address(&mut data)?,
+		})
+	}
+	
+	pub fn to_bytes(&self) -> Box<[u8]> {
+		let mut data = Vec::with_capacity(13);
+		data.push(b'5');
+		data.extend(encode_ascii_hex(self.id));
+		data.extend_from_slice(self.destination.as_bytes());
+		data.extend_from_slice(self.origin.as_bytes());
+		
+		data.into()
+	}
+}
+
+/// Broadcast periodically to prove liveness and share metadata with neighbors
+#[derive(Debug)]
+pub struct HelloPacket {
+	pub sequence: u16,
+	/// Longest time until the sender's next hello, sent in tenths of a second
+	pub interval: Duration,
+	pub info: NodeInfo,
+	/// Neighbors the sender currently has a link to
+	pub neighbors: Vec<ATAddress>,
+}
+
+impl HelloPacket {
+	/// Limits the neighbor list, so hellos of nodes in dense networks still fit into a frame
+	pub const MAX_NEIGHBORS: usize = 16;
+	
+	fn parse_from(mut data: &[u8]) -> Result<Self, io::Error> {
+		let sequence = take_int(&mut data, 4)?;
+		let interval = Duration::from_millis(100 * take_int::<u16>(&mut data, 4)? as u64);
+		let capabilities = Capabilities::from_bits(take_int(&mut data, 2)?);
+		
+		let battery = match take_int(&mut data, 2)? {
+			0xFF => None,
+			battery => Some(battery),
+		};
+		
+		let neighbor_count: u8 = take_int(&mut data, 2)?;
+		
+		let neighbors = (0..neighbor_count)
+			.map(|_| take_address(&mut data))
+			.collect::<Result<_, _>>()?;
+		
+		// the name takes up the rest of the packet
+		let name = if data.is_empty() {
+			None
+		} else {
+			Some(String::from_utf8_lossy(data).into_owned())
+		};
+		
+		Ok(Self {
+			sequence,
+			interval,
+			info: NodeInfo {
+				name,
+				capabilities,
+				battery,
+			},
+			neighbors,
+		})
+	}
+	
+	pub fn to_bytes(&self) -> Box<[u8]> {
+		let neighbors = &self.neighbors[..self.neighbors.len().min(Self::MAX_NEIGHBORS)];
+		let name = self.info.name.as_deref()
+			.map(truncate_name)
+			.unwrap_or_default();
+		
+		let interval = (self.interval.as_millis() / 100).min(u16::MAX as u128) as u16;
+		
+		let mut data = Vec::with_capacity(15 + 4 * neighbors.len() + name.len());
+		data.push(b'6');
+		data.extend(encode_ascii_hex(self.sequence));
+		data.extend(encode_ascii_hex(interval));
+		data.extend(encode_ascii_hex(self.info.capabilities.to_bits()));
+		data.extend(encode_ascii_hex(self.info.battery.map(|battery| battery.min(100)).unwrap_or(0xFF)));
+		data.extend(encode_ascii_hex(neighbors.len() as u8));
+		
+		for neighbor in neighbors {
+			data.extend_from_slice(neighbor.as_bytes());
+		}
+		
+		data.extend_from_slice(name.as_bytes());
+		
+		data.into()
+	}
+}
+
+fn truncate_name(name: &str) -> &str {
+	let mut length = name.len().min(MAX_NAME_LENGTH);
+	
+	while !name.is_char_boundary(length) {
+		length -= 1;
+	}
+	
+	&name[..length]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn message(address: &[u8; 4], data: &[u8]) -> ATMessage {
+		ATMessage {
+			address: ATAddress::new(*address).unwrap(),
+			data: data.into(),
+			signal: None,
+		}
+	}
+	
+	#[test]
+	fn packet_without_link_header() {
+		let packet = parse_packet(&message(b"0001", b"20002")).unwrap();
+		
+		assert_eq!(packet.link_id, None);
+		assert!(matches!(packet.body, AODVPacketBody::RouteError(RouteErrorPacket { destination }) if destination.as_bytes() == b"0002"));
+	}
+	
+	#[test]
+	fn packet_with_link_header() {
+		let error = RouteErrorPacket {
+			destination: ATAddress::new(*b"0002").unwrap(),
+		};
+		let frame = with_link_header(0x12AB, &error.to_bytes());
+		
+		assert_eq!(&*frame, b"A12AB20002");
+		
+		let packet = parse_packet(&message(b"0001", &frame)).unwrap();
+		
+		assert_eq!(packet.link_id, Some(0x12AB));
+		assert!(matches!(packet.body, AODVPacketBody::RouteError(_)));
+	}
+	
+	#[test]
+	fn packet_type_of_frame() {
+		assert_eq!(PacketType::of_frame(b"20002"), Some(PacketType::RouteError));
+		assert_eq!(PacketType::of_frame(b"A12AB400FF"), Some(PacketType::LinkAck));
+		assert_eq!(PacketType::of_frame(b"A12"), None);
+		assert_eq!(PacketType::of_frame(b"9"), None);
+	}
+	
+	#[test]
+	fn link_ack() {
+		let ack = LinkAckPacket {
+			link_id: 0x00FF,
+		};
+		
+		assert_eq!(&*ack.to_bytes(), b"400FF");
+		
+		let packet = parse_packet(&message(b"0001", &ack.to_bytes())).unwrap();
+		
+		assert!(matches!(packet.body, AODVPacketBody::LinkAck(LinkAckPacket { link_id: 0x00FF })));
+	}
+	
+	#[test]
+	fn route_request() {
+		let request = RouteRequestPacket {
+			hop_count: 2,
+			metric: 0x0014,
+			id: 0x0010,
+			destination: ATAddress::new(*b"0009").unwrap(),
+			destination_sequence: None,
+			origin: ATAddress::new(*b"0001").unwrap(),
+			origin_sequence: 0x0020,
+			first_hop: ATAddress::new(*b"0003").unwrap(),
+		};
+		
+		assert_eq!(&*request.to_bytes(), b"0Y020014001000090000000100200003");
+		
+		let packet = parse_packet(&message(b"0004", &request.to_bytes())).unwrap();
+		
+		let AODVPacketBody::RouteRequest(parsed) = packet.body else {
+			panic!("expected RouteRequestPacket");
+		};
+		
+		assert_eq!(parsed.metric, 0x0014);
+		assert_eq!(parsed.destination_sequence, None);
+		assert_eq!(parsed.origin_sequence, 0x0020);
+		assert_eq!(parsed.first_hop, request.first_hop);
+	}
+	
+	#[test]
+	fn data() {
+		let data = DataPacket {
+			id: 0x0102,
+			ack_requested: true,
+			hop_count: 3,
+			destination: ATAddress::new(*b"0002").unwrap(),
+			origin: ATAddress::new(*b"0001").unwrap(),
+			payload: b"Hello"[..].into(),
+		};
+		
+		assert_eq!(&*data.to_bytes(), b"3Y01020300020001Hello");
+		
+		let packet = parse_packet(&message(b"0001", &data.to_bytes())).unwrap();
+		
+		let AODVPacketBody::Data(parsed) = packet.body else {
+			panic!("expected DataPacket");
+		};
+		
+		assert_eq!(parsed.id, 0x0102);
+		assert!(parsed.ack_requested);
+		assert_eq!(parsed.hop_count, 3);
+		assert_eq!(parsed.destination, data.destination);
+		assert_eq!(parsed.origin, data.origin);
+		assert_eq!(parsed.payload, data.payload);
+	}
+	
+	#[test]
+	fn data_ack() {
+		let ack = DataAckPacket {
+			id: 0xBEEF,
+			destination: ATAddress::new(*b"0001").unwrap(),
+			origin: ATAddress::new(*b"0002").unwrap(),
+		};
+		
+		assert_eq!(&*ack.to_bytes(), b"5BEEF00010002");
+		
+		let packet = parse_packet(&message(b"0002", &ack.to_bytes())).unwrap();
+		
+		assert!(matches!(packet.body, AODVPacketBody::DataAck(DataAckPacket { id: 0xBEEF, .. })));
+	}
+	
+	#[test]
+	fn hello() {
+		let hello = HelloPacket {
+			sequence: 0x0007,
+			interval: Duration::from_secs(10),
+			info: NodeInfo {
+				name: Some("Gateway".to_owned()),
+				capabilities: Capabilities {
+					gateway: true,
+					sleepy: false,
+					relay: true,
+				},
+				battery: Some(80),
+			},
+			neighbors: vec![ATAddress::new(*b"0002").unwrap(), ATAddress::new(*b"0003").unwrap()],
+		};
+		
+		assert_eq!(&*hello.to_bytes(), b"60007006405500200020003Gateway");
+		
+		let packet = parse_packet(&message(b"0001", &hello.to_bytes())).unwrap();
+		
+		let AODVPacketBody::Hello(parsed) = packet.body else {
+			panic!("expected HelloPacket");
+		};
+		
+		assert_eq!(parsed.sequence, hello.sequence);
+		assert_eq!(parsed.interval, hello.interval);
+		assert_eq!(parsed.info, hello.info);
+		assert_eq!(parsed.neighbors, hello.neighbors);
+	}
+	
+	#[test]
+	fn hello_without_metadata() {
+		let hello = HelloPacket {
+			sequence: 0x0001,
+			interval: Duration::from_millis(1500),
+			info: NodeInfo::default(),
+			neighbors: Vec::new(),
+		};
+		
+		assert_eq!(&*hello.to_bytes(), b"60001000F00FF00");
+		
+		let AODVPacketBody::Hello(parsed) = parse_packet(&message(b"0001", &hello.to_bytes())).unwrap().body else {
+			panic!("expected HelloPacket");
+		};
+		
+		assert_eq!(parsed.info, NodeInfo::default());
+	}
+	
+	#[test]
+	fn truncates_long_names() {
+		assert_eq!(truncate_name("short"), "short");
+		assert_eq!(truncate_name(&"ä".repeat(20)).len(), MAX_NAME_LENGTH);
+		assert_eq!(truncate_name(&format!("a{}", "ä".repeat(20))).len(), MAX_NAME_LENGTH - 1);
+	}
+	
+	#[test]
+	fn truncated_link_header() {
+		assert!(parse_packet(&message(b"0001", b"A12")).is_err());
+		assert!(parse_packet(&message(b"0001", b"A12AB")).is_err());
+	}
 }
\ No newline at end of file
@@ -0,0 +1,323 @@
+use std::{error::Error, fmt::{self, Display}, time::Duration};
+
+use super::{BroadcastSuppression, DeliveryConfig, FloodingConfig, HelloIntervalConfig, LinkAckConfig, RouteRequestLimits};
+
+/// Route requests and link-layer frames are sent again at most this many times, as each retry doubles the wait
+const MAX_RETRIES: u8 = 10;
+
+/// Timing and protocol parameters of an `AODVProtocol`, named after RFC 3561 section 10 where it defines them
+#[derive(Debug, Clone, Copy)]
+pub struct AODVParameters {
+	pub hello_interval: HelloIntervalConfig,
+	/// How long a neighbor sending hellos every `hello_interval.min` may stay silent before the link goes down,
+	/// ALLOWED_HELLO_LOSS * HELLO_INTERVAL in the RFC
+	pub hello_timeout: Duration,
+	/// Discovered routes that weren't confirmed or used for data for this long expire
+	pub active_route_timeout: Duration,
+	/// Most hops a route request travels
+	pub net_diameter: u8,
+	/// Estimated time a packet takes per hop, including queueing and time on air
+	pub node_traversal_time: Duration,
+	/// Route requests sent again before a discovery fails, waiting twice as long every time
+	pub rreq_retries: u8,
+	/// Link-layer acknowledgements from the next hop, if at all
+	pub link_acks: Option<LinkAckConfig>,
+	pub delivery: DeliveryConfig,
+	pub flooding: FloodingConfig,
+	pub route_request_limits: RouteRequestLimits,
+	/// Most data sent from this node that may wait for a route at once, further data fails right away
+	pub max_queued_messages: usize,
+}
+
+impl AODVParameters {
+	/// The values RFC 3561 suggests, for fast links with short delays
+	pub fn rfc3561() -> Self {
+		let node_traversal_time = Duration::from_millis(40);
+		let net_diameter = 35;
+		let rreq_retries = 2;
+		
+		Self {
+			hello_interval: HelloIntervalConfig {
+				min: Duration::from_secs(1),
+				max: Duration::from_secs(1),
+			},
+			hello_timeout: Duration::from_secs(2),
+			active_route_timeout: Duration::from_secs(3),
+			net_diameter,
+			node_traversal_time,
+			rreq_retries,
+			link_acks: None,
+			delivery: DeliveryConfig {
+				timeout: discovery_time(net_traversal_time(node_traversal_time, net_diameter), rreq_retries),
+				max_retries: 2,
+			},
+			flooding: FloodingConfig {
+				max_jitter: Duration::from_millis(10),
+				suppression: BroadcastSuppression::None,
+			},
+			route_request_limits: RouteRequestLimits::default(),
+			max_queued_messages: 64,
+		}
+	}
+	
+	/// For LoRa at spreading factor 7 and 125 kHz, where a frame spends tens of milliseconds on air
+	pub fn lora() -> Self {
+		Self {
+			hello_interval: HelloIntervalConfig {
+				min: Duration::from_secs(10),
+				max: Duration::from_secs(60),
+			},
+			hello_timeout: Duration::from_secs(25),
+			active_route_timeout: Duration::from_secs(300),
+			net_diameter: 10,
+			// half the flooding jitter on average, plus time on air and the serial round trips to the module
+			node_traversal_time: Duration::from_millis(500),
+			rreq_retries: 2,
+			link_acks: Some(LinkAckConfig {
+				timeout: Duration::from_secs(4),
+				max_retries: 3,
+			}),
+			delivery: DeliveryConfig {
+				timeout: Duration::from_secs(75),
+				max_retries: 2,
+			},
+			flooding: FloodingConfig {
+				max_jitter: Duration::from_millis(500),
+				suppression: BroadcastSuppression::Counter {
+					threshold: 3,
+				},
+			},
+			route_request_limits: RouteRequestLimits::default(),
+			max_queued_messages: 32,
+		}
+	}
+	
+	/// For LoRa at spreading factor 12, where a frame spends more than a second on air and duty cycle limits bite
+	pub fn lora_long_range() -> Self {
+		let lora = Self::lora();
+		
+		Self {
+			hello_interval: HelloIntervalConfig {
+				min: Duration::from_secs(60),
+				max: Duration::from_secs(300),
+			},
+			hello_timeout: Duration::from_secs(150),
+			active_route_timeout: Duration::from_secs(1800),
+			net_diameter: 6,
+			node_traversal_time: Duration::from_secs(5),
+			// every route request floods the whole network, which takes minutes at this spreading factor
+			rreq_retries: 1,
+			link_acks: Some(LinkAckConfig {
+				timeout: Duration::from_secs(15),
+				max_retries: 3,
+			}),
+			delivery: DeliveryConfig {
+				timeout: Duration::from_secs(180),
+				max_retries: 2,
+			},
+			flooding: FloodingConfig {
+				max_jitter: Duration::from_secs(3),
+				..lora.flooding
+			},
+			max_queued_messages: 16,
+			..lora
+		}
+	}
+	
+	/// Longest time a route request and its reply are expected to take, NET_TRAVERSAL_TIME in the RFC
+	pub fn net_traversal_time(&self) -> Duration {
+		net_traversal_time(self.node_traversal_time, self.net_diameter)
+	}
+	
+	/// Longest time a route discovery takes with all its retries, after which the data waiting for it fails
+	pub fn discovery_time(&self) -> Duration {
+		discovery_time(self.net_traversal_time(), self.rreq_retries)
+	}
+	
+	/// Checks that the values are usable together
+	pub fn validate(&self) -> Result<(), ParameterError> {
+		let hello_interval = self.hello_interval;
+		
+		if hello_interval.min.is_zero() || hello_interval.min > hello_interval.max {
+			return Err(ParameterError::HelloInterval);
+		}
+		
+		if self.hello_timeout <= hello_interval.min {
+			return Err(ParameterError::HelloTimeoutTooShort);
+		}
+		
+		if self.net_diameter == 0 || self.node_traversal_time.is_zero() {
+			return Err(ParameterError::NetTraversalTime);
+		}
+		
+		// a route has to outlive the discovery that found it
+		if self.active_route_timeout <= self.net_traversal_time() {
+			return Err(ParameterError::ActiveRouteTimeoutTooShort);
+		}
+		
+		if self.link_acks.is_some_and(|link_acks| link_acks.timeout.is_zero()) || self.delivery.timeout.is_zero() {
+			return Err(ParameterError::ZeroTimeout);
+		}
+		
+		if self.rreq_retries > MAX_RETRIES || self.link_acks.is_some_and(|link_acks| link_acks.max_retries > MAX_RETRIES) {
+			return Err(ParameterError::TooManyRetries);
+		}
+		
+		// data waiting for a route shouldn't time out while the discovery is still retrying
+		if self.delivery.timeout < self.discovery_time() {
+			return Err(ParameterError::DeliveryTimeoutTooShort);
+		}
+		
+		if let BroadcastSuppression::Probabilistic { probability } = self.flooding.suppression {
+			if !(0.0..=1.0).contains(&probability) {
+				return Err(ParameterError::SuppressionProbability);
+			}
+		}
+		
+		if self.max_queued_messages == 0 {
+			return Err(ParameterError::NoQueue);
+		}
+		
+		Ok(())
+	}
+}
+
+impl Default for AODVParameters {
+	fn default() -> Self {
+		Self::rfc3561()
+	}
+}
+
+fn net_traversal_time(node_traversal_time: Duration, net_diameter: u8) -> Duration {
+	node_traversal_time * 2 * net_diameter as u32
+}
+
+/// The wait for a reply doubles with every retry
+fn discovery_time(net_traversal_time: Duration, rreq_retries: u8) -> Duration {
+	net_traversal_time.saturating_mul(2u32.saturating_pow(rreq_retries as u32 + 1) - 1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterError {
+	/// The minimum hello interval is 0 or larger than the maximum
+	HelloInterval,
+	/// The hello timeout doesn't leave neighbors time to send a single hello
+	HelloTimeoutTooShort,
+	/// The net diameter or the node traversal time is 0
+	NetTraversalTime,
+	/// Routes would expire before a route discovery can finish
+	ActiveRouteTimeoutTooShort,
+	/// A link-layer or delivery timeout is 0
+	ZeroTimeout,
+	/// Route requests or link-layer frames would be retried so often that the doubled waits overflow
+	TooManyRetries,
+	/// Data waiting for a route would time out before the route discovery gives up
+	DeliveryTimeoutTooShort,
+	/// The rebroadcast probability is not a number between 0 and 1
+	SuppressionProbability,
+	/// No data could wait for a route
+	NoQueue,
+}
+
+impl Display for ParameterError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParameterError::HelloInterval => write!(f, "minimum hello interval must be above 0 and not above the maximum"),
+			ParameterError::HelloTimeoutTooShort => write!(f, "hello timeout must be longer than the minimum hello interval"),
+			ParameterError::NetTraversalTime => write!(f, "net diameter and node traversal time must be above 0"),
+			ParameterError::ActiveRouteTimeoutTooShort => write!(f, "active route timeout must be longer than the net traversal time"),
+			ParameterError::ZeroTimeout => write!(f, "link acknowledgement and delivery timeouts must be above 0"),
+			ParameterError::TooManyRetries => write!(f, "route requests and link-layer frames may be retried at most {MAX_RETRIES} times"),
+			ParameterError::DeliveryTimeoutTooShort => write!(f, "delivery timeout must not be shorter than a route discovery with all retries"),
+			ParameterError::SuppressionProbability => write!(f, "rebroadcast probability must be between 0 and 1"),
+			ParameterError::NoQueue => write!(f, "at least one message must be able to wait for a route"),
+		}
+	}
+}
+
+impl Error for ParameterError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn presets_are_valid() {
+		assert_eq!(AODVParameters::rfc3561().validate(), Ok(()));
+		assert_eq!(AODVParameters::lora().validate(), Ok(()));
+		assert_eq!(AODVParameters::lora_long_range().validate(), Ok(()));
+		
+		assert_eq!(AODVParameters::rfc3561().net_traversal_time(), Duration::from_millis(2800));
+		
+		// the first request and two retries, waiting one, two and four net traversal times
+		assert_eq!(AODVParameters::rfc3561().discovery_time(), Duration::from_millis(2800 * 7));
+		assert_eq!(AODVParameters::lora().discovery_time(), Duration::from_secs(70));
+	}
+	
+	#[test]
+	fn rejects_inconsistent_values() {
+		let parameters = AODVParameters {
+			hello_timeout: Duration::from_secs(10),
+			..AODVParameters::lora()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::HelloTimeoutTooShort));
+		
+		let parameters = AODVParameters {
+			hello_interval: HelloIntervalConfig {
+				min: Duration::from_secs(60),
+				max: Duration::from_secs(10),
+			},
+			..AODVParameters::lora()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::HelloInterval));
+		
+		let parameters = AODVParameters {
+			active_route_timeout: Duration::from_secs(2),
+			..AODVParameters::rfc3561()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::ActiveRouteTimeoutTooShort));
+		
+		let parameters = AODVParameters {
+			max_queued_messages: 0,
+			..AODVParameters::rfc3561()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::NoQueue));
+		
+		let parameters = AODVParameters {
+			rreq_retries: u8::MAX,
+			..AODVParameters::rfc3561()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::TooManyRetries));
+		
+		let parameters = AODVParameters {
+			link_acks: Some(LinkAckConfig {
+				timeout: Duration::from_secs(4),
+				max_retries: 40,
+			}),
+			..AODVParameters::lora()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::TooManyRetries));
+		
+		let parameters = AODVParameters {
+			delivery: DeliveryConfig {
+				timeout: Duration::from_secs(30),
+				max_retries: 3,
+			},
+			..AODVParameters::lora()
+		};
+		assert_eq!(parameters.validate(), Err(ParameterError::DeliveryTimeoutTooShort));
+		
+		for probability in [f32::NAN, -0.5, 1.5] {
+			let parameters = AODVParameters {
+				flooding: FloodingConfig {
+					max_jitter: Duration::from_millis(500),
+					suppression: BroadcastSuppression::Probabilistic {
+						probability,
+					},
+				},
+				..AODVParameters::lora()
+			};
+			assert_eq!(parameters.validate(), Err(ParameterError::SuppressionProbability));
+		}
+	}
+}
\ No newline at end of file
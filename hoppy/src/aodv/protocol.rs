@@ -0,0 +1,1929 @@
+use std::{collections::{BTreeMap, BTreeSet, VecDeque}, mem, time::{Duration, Instant}};
+
+use rand::{rngs::StdRng, SeedableRng};
+use tracing::{debug, error, info, warn};
+
+use crate::at_module::{ATMessage, ATSignal, at_address::ATAddress};
+
+use super::{
+	delivery::is_final_status,
+	events::{AODVEvent, DropReason},
+	flooding::{BroadcastQueue, FloodKey},
+	hello_schedule::{HelloSchedule, with_jitter},
+	message_queue::ReceivedMessage,
+	metric::RouteMetric,
+	neighbor_filter::NeighborFilter,
+	neighbor_table::{NeighborEvent, NeighborStats, NeighborTable},
+	node_info::NodeInfo,
+	packets::*,
+	parameters::{AODVParameters, ParameterError},
+	rate_limit::{RouteRequestLimiter, Throttling},
+	routing_snapshot::RoutingSnapshot,
+	routing_table::{Route, RoutingTable, StaticRoute},
+	stats::{count, AODVStats, QueueDepths},
+	sequence_number_newer,
+	DeliveryFailure,
+	DeliveryStatus,
+};
+
+/// How often link acknowledgements, deliveries and neighbor liveness are checked
+const MAINTENANCE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long frames from neighbors using link acknowledgements are remembered if this node doesn't use them itself
+const SEEN_LINK_FRAME_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Timer {
+	Hello,
+	/// Retransmissions, delivery timeouts and neighbor liveness
+	Maintenance,
+	/// Jittered rebroadcasts of flooded packets
+	Broadcast,
+}
+
+#[derive(Debug)]
+pub enum Action {
+	Send {
+		next_hop: ATAddress,
+		frame: Box<[u8]>,
+	},
+	Broadcast {
+		frame: Box<[u8]>,
+	},
+	/// Data addressed to this node arrived
+	Deliver(ReceivedMessage),
+	Event(AODVEvent),
+	/// Fire `timer` at `at`, replacing any earlier schedule of the same timer
+	ScheduleTimer {
+		timer: Timer,
+		at: Instant,
+	},
+	/// The status of data passed to `AODVProtocol::send` changed
+	UpdateDelivery {
+		destination: ATAddress,
+		id: u16,
+		status: DeliveryStatus,
+	},
+}
+
+struct PendingDelivery {
+	payload: Box<[u8]>,
+	reliable: bool,
+	status: DeliveryStatus,
+	retries: u8,
+	deadline: Instant,
+}
+
+/// A route discovery for data originating from this node
+struct Discovery {
+	started: Instant,
+	/// Route requests sent so far
+	requests: u8,
+	/// When to send the next route request or give up
+	deadline: Instant,
+}
+
+struct PendingLinkAck {
+	next_hop: ATAddress,
+	/// Destination and id of the DataPacket contained in the frame, if it originated from this node
+	delivery: Option<(ATAddress, u16)>,
+	frame: Box<[u8]>,
+	retries: u8,
+	deadline: Instant,
+}
+
+fn next_id(current: &mut u16) -> u16 {
+	let id = *current;
+	*current = current.wrapping_add(1);
+	id
+}
+
+/// The AODV state machine without any I/O, frames, timers and send requests go in and actions come out
+pub struct AODVProtocol {
+	address: ATAddress,
+	/// Sequence number this node replied with, if it is the destination of the request
+	seen_requests: BTreeMap<(ATAddress, u16), Option<u16>>,
+	routing_table: RoutingTable,
+	outbound_messages: BTreeMap<ATAddress, Vec<DataPacket>>,
+	/// Ongoing discoveries, until a route is found or all data waiting for one failed
+	discoveries: BTreeMap<ATAddress, Discovery>,
+	pending_deliveries: BTreeMap<(ATAddress, u16), PendingDelivery>,
+	seen_data: BTreeMap<(ATAddress, u16), Instant>,
+	pending_link_acks: BTreeMap<u16, PendingLinkAck>,
+	seen_link_frames: BTreeMap<(ATAddress, u16), Instant>,
+	pending_broadcasts: BroadcastQueue,
+	request_limiter: RouteRequestLimiter,
+	neighbor_filter: NeighborFilter,
+	node_info: NodeInfo,
+	hello_schedule: HelloSchedule,
+	/// Set when a link went up or down, so the next hello is sent sooner
+	neighborhood_changed: bool,
+	last_hello: Instant,
+	next_hello: Instant,
+	/// Interval announced in the last hello
+	advertised_interval: Duration,
+	/// When a route request or error was last broadcast, which all neighbors in range could hear
+	last_broadcast: Option<Instant>,
+	current_route_request_id: u16,
+	current_sequence_number: u16,
+	current_link_id: u16,
+	parameters: AODVParameters,
+	metric: Box<dyn RouteMetric>,
+	rng: StdRng,
+	stats: AODVStats,
+	actions: VecDeque<Action>,
+}
+
+impl AODVProtocol {
+	pub fn new(address: ATAddress, parameters: AODVParameters, metric: Box<dyn RouteMetric>, node_info: NodeInfo, now: Instant) -> Result<Self, ParameterError> {
+		parameters.validate()?;
+		
+		let mut protocol = Self {
+			address,
+			seen_requests: BTreeMap::new(),
+			routing_table: RoutingTable::new(address, NeighborTable::new(parameters.hello_interval.min, parameters.hello_timeout), now),
+			outbound_messages: BTreeMap::new(),
+			discoveries: BTreeMap::new(),
+			pending_deliveries: BTreeMap::new(),
+			seen_data: BTreeMap::new(),
+			pending_link_acks: BTreeMap::new(),
+			seen_link_frames: BTreeMap::new(),
+			pending_broadcasts: BroadcastQueue::new(parameters.flooding),
+			request_limiter: RouteRequestLimiter::new(parameters.route_request_limits),
+			neighbor_filter: NeighborFilter::AllowAll,
+			node_info,
+			hello_schedule: HelloSchedule::new(parameters.hello_interval),
+			// start with the minimum interval
+			neighborhood_changed: true,
+			last_hello: now,
+			next_hello: now,
+			advertised_interval: parameters.hello_interval.min,
+			last_broadcast: None,
+			current_route_request_id: 0,
+			current_sequence_number: 0,
+			current_link_id: 0,
+			parameters,
+			metric,
+			rng: StdRng::from_entropy(),
+			stats: AODVStats::new(now),
+			actions: VecDeque::new(),
+		};
+		
+		protocol.schedule_timer(Timer::Hello, now);
+		protocol.schedule_timer(Timer::Maintenance, now + MAINTENANCE_INTERVAL);
+		
+		Ok(protocol)
+	}
+	
+	/// Makes jitter reproducible
+	pub fn seed_rng(&mut self, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+	
+	pub fn address(&self) -> ATAddress {
+		self.address
+	}
+	
+	/// Returns the next action the driver has to perform
+	pub fn poll_action(&mut self) -> Option<Action> {
+		let action = self.actions.pop_front()?;
+		
+		if let Action::Send { frame, .. } | Action::Broadcast { frame } = &action {
+			if let Some(packet_type) = PacketType::of_frame(frame) {
+				count(&mut self.stats.frames_sent, packet_type);
+			}
+		}
+		
+		Some(action)
+	}
+	
+	pub fn stats(&self) -> &AODVStats {
+		&self.stats
+	}
+	
+	/// Starts counting from zero, returning the counters up to now
+	pub fn reset_stats(&mut self, now: Instant) -> AODVStats {
+		mem::replace(&mut self.stats, AODVStats::new(now))
+	}
+	
+	/// Link statistics of all nodes this node received frames from recently
+	pub fn neighbor_stats(&mut self, now: Instant) -> Vec<NeighborStats> {
+		self.routing_table.neighbors_mut()
+			.stats(now)
+	}
+	
+	/// Nodes that are not neighbors but can be reached over one, each with the neighbors that can reach it
+	pub fn two_hop_neighbors(&self) -> BTreeMap<ATAddress, BTreeSet<ATAddress>> {
+		self.routing_table.neighbors()
+			.two_hop_neighbors(self.address)
+	}
+	
+	/// Neighbors whose route requests are dropped for exceeding the rate limit, with the time they are dropped until
+	pub fn throttled_neighbors(&self, now: Instant) -> Vec<(ATAddress, Instant)> {
+		self.request_limiter.throttled_neighbors(now)
+	}
+	
+	/// Sends everything for `destination` over `route.next_hop` until unpinned, regardless of discovered routes and link failures
+	/// 
+	/// Returns false if `destination` is this node.
+	pub fn pin_route(&mut self, destination: ATAddress, route: StaticRoute, now: Instant) -> bool {
+		if destination == self.address {
+			return false;
+		}
+		
+		let is_known = self.routing_table.pin_route(destination, route, now)
+			.is_some();
+		
+		let route = self.routing_table.get_route(destination, None)
+			.expect("pinned route should be preferred");
+		
+		self.report_route(destination, route, is_known);
+		self.send_outbound_messages(destination, route, now);
+		
+		true
+	}
+	
+	/// Goes back to discovered routes for `destination`, returning the route that was pinned
+	pub fn unpin_route(&mut self, destination: ATAddress, now: Instant) -> Option<StaticRoute> {
+		let route = self.routing_table.unpin_route(destination)?;
+		
+		self.emit(AODVEvent::RouteInvalidated {
+			destination,
+			next_hop: route.next_hop,
+		});
+		
+		// others might have relied on the pinned route
+		if self.routing_table.get_route(destination, None).is_none() {
+			let packet = RouteErrorPacket {
+				destination,
+			};
+			
+			self.broadcast(packet.to_bytes(), now);
+		}
+		
+		Some(route)
+	}
+	
+	pub fn static_routes(&self) -> Vec<(ATAddress, StaticRoute)> {
+		self.routing_table.static_routes()
+			.collect()
+	}
+	
+	/// Routes to all other nodes this node knows of
+	pub fn routing_snapshot(&self, now: Instant) -> RoutingSnapshot {
+		self.routing_table.snapshot(now)
+	}
+	
+	/// Number of stored routes, not counting routes to neighbors over the direct link
+	pub fn route_count(&self) -> usize {
+		self.routing_table.route_count()
+	}
+	
+	pub fn queue_depths(&self) -> QueueDepths {
+		QueueDepths {
+			outbound_messages: self.outbound_messages.values().map(Vec::len).sum(),
+			pending_deliveries: self.pending_deliveries.len(),
+			pending_link_acks: self.pending_link_acks.len(),
+			pending_broadcasts: self.pending_broadcasts.len(),
+		}
+	}
+	
+	/// Changes the metadata advertised in the following hellos, for example to report a new battery level
+	pub fn set_node_info(&mut self, node_info: NodeInfo) {
+		self.node_info = node_info;
+	}
+	
+	/// Ignores frames from the senders the filter doesn't allow, taking down the links to them right away
+	pub fn set_neighbor_filter(&mut self, neighbor_filter: NeighborFilter, now: Instant) {
+		self.neighbor_filter = neighbor_filter;
+		
+		let filtered_neighbors: Vec<_> = self.routing_table.neighbors()
+			.up_neighbors()
+			.into_iter()
+			.filter(|&neighbor| !self.neighbor_filter.allows(neighbor))
+			.collect();
+		
+		for neighbor in filtered_neighbors {
+			self.break_link(neighbor, false, now);
+		}
+	}
+	
+	pub fn neighbor_filter(&self) -> &NeighborFilter {
+		&self.neighbor_filter
+	}
+	
+	/// Whether data sent from or through this node still waits for a route or an acknowledgement
+	pub fn has_pending_data(&self) -> bool {
+		!self.pending_deliveries.is_empty() || !self.pending_link_acks.is_empty()
+	}
+	
+	/// Tells neighbors this node is leaving, so they drop their routes over it without waiting for hellos to time out
+	pub fn say_goodbye(&mut self, now: Instant) {
+		let packet = RouteErrorPacket {
+			destination: self.address,
+		};
+		
+		self.broadcast(packet.to_bytes(), now);
+	}
+	
+	fn schedule_timer(&mut self, timer: Timer, at: Instant) {
+		self.actions.push_back(Action::ScheduleTimer {
+			timer,
+			at,
+		});
+	}
+	
+	fn emit(&mut self, event: AODVEvent) {
+		self.actions.push_back(Action::Event(event));
+	}
+	
+	pub fn handle_timer(&mut self, timer: Timer, now: Instant) {
+		match timer {
+			Timer::Hello => self.handle_hello_timer(now),
+			Timer::Maintenance => {
+				self.check_neighbor_hello(now);
+				self.check_link_acks(now);
+				self.check_deliveries(now);
+				self.check_discoveries(now);
+				self.expire_routes(now);
+				
+				self.schedule_timer(Timer::Maintenance, now + MAINTENANCE_INTERVAL);
+			},
+			Timer::Broadcast => self.send_due_broadcasts(now),
+		}
+	}
+	
+	/// Starts delivering `payload` to `destination`, `id` must not be reused while an earlier delivery is pending
+	///
+	/// Ids are chosen by the caller, so it can hand out a delivery handle before the protocol processed the request.
+	pub fn send(&mut self, destination: ATAddress, id: u16, payload: Box<[u8]>, reliable: bool, now: Instant) {
+		self.stats.data_originated += 1;
+		
+		self.pending_deliveries.insert((destination, id), PendingDelivery {
+			payload: payload.clone(),
+			reliable,
+			status: DeliveryStatus::Queued,
+			retries: 0,
+			// unreliable deliveries also time out if no route can be found
+			deadline: now + self.parameters.delivery.timeout,
+		});
+		
+		let packet = DataPacket {
+			id,
+			ack_requested: reliable,
+			hop_count: 0,
+			destination,
+			origin: self.address,
+			payload,
+		};
+		
+		self.send_data(packet, now);
+	}
+	
+	fn update_delivery(&mut self, delivery: (ATAddress, u16), status: DeliveryStatus) {
+		let Some(pending) = self.pending_deliveries.get_mut(&delivery) else {
+			return;
+		};
+		
+		if pending.status == status {
+			return;
+		}
+		
+		pending.status = status;
+		
+		if let DeliveryStatus::Failed(failure) = status {
+			count(&mut self.stats.deliveries_failed, failure);
+		}
+		
+		let (destination, id) = delivery;
+		
+		self.actions.push_back(Action::UpdateDelivery {
+			destination,
+			id,
+			status,
+		});
+		
+		if is_final_status(status, pending.reliable) {
+			self.pending_deliveries.remove(&delivery);
+		}
+	}
+	
+	fn fail_delivery(&mut self, delivery: (ATAddress, u16), failure: DeliveryFailure) {
+		self.update_delivery(delivery, DeliveryStatus::Failed(failure));
+		
+		let (destination, id) = delivery;
+		
+		if let Some(queue) = self.outbound_messages.get_mut(&destination) {
+			queue.retain(|queued| queued.id != id);
+		}
+		
+		let is_queue_empty = self.outbound_messages.get(&destination)
+			.is_none_or(|queue| queue.is_empty());
+		
+		if is_queue_empty && self.discoveries.remove(&destination).is_some() {
+			self.stats.discoveries_failed += 1;
+			self.emit(AODVEvent::DiscoveryFailed {
+				destination,
+			});
+		}
+	}
+	
+	fn send_data(&mut self, packet: DataPacket, now: Instant) {
+		let delivery = (packet.destination, packet.id);
+		let destination = packet.destination;
+		
+		if let Some(route) = self.routing_table.get_route(destination, None) {
+			self.routing_table.refresh_route(destination, route.next_hop, now);
+			self.transmit_data(route.next_hop, &packet, now);
+			return;
+		}
+		
+		// a retransmission might still be waiting for a route
+		let is_queued = self.outbound_messages.get(&destination)
+			.is_some_and(|queue| queue.iter().any(|queued| queued.id == packet.id));
+		
+		if !is_queued {
+			let queued_messages: usize = self.outbound_messages.values()
+				.map(Vec::len)
+				.sum();
+			
+			if queued_messages >= self.parameters.max_queued_messages {
+				warn!(%destination, id = format_args!("{:04X}", packet.id), "Too much data is waiting for routes, dropping message");
+				self.fail_delivery(delivery, DeliveryFailure::QueueFull);
+				return;
+			}
+			
+			self.outbound_messages.entry(destination)
+				.or_default()
+				.push(packet);
+		}
+		
+		self.update_delivery(delivery, DeliveryStatus::Queued);
+		
+		// retries of an ongoing discovery are sent by the maintenance timer
+		if self.discoveries.contains_key(&destination) {
+			return;
+		}
+		
+		if !self.request_route(destination, now) {
+			// the packet is queued anyway, a route might still be found through another request
+			warn!(%destination, "Route request rate limit exceeded, not requesting a route");
+			return;
+		}
+		
+		self.discoveries.insert(destination, Discovery {
+			started: now,
+			requests: 1,
+			deadline: now + self.parameters.net_traversal_time(),
+		});
+		
+		self.stats.discoveries_started += 1;
+		self.emit(AODVEvent::DiscoveryStarted {
+			destination,
+		});
+	}
+	
+	/// Broadcasts a route request for `destination`, returns false if the rate limit doesn't allow it
+	fn request_route(&mut self, destination: ATAddress, now: Instant) -> bool {
+		if !self.request_limiter.allow_own(now) {
+			return false;
+		}
+		
+		let request = RouteRequestPacket {
+			id: next_id(&mut self.current_route_request_id),
+			hop_count: 0,
+			destination,
+			destination_sequence: self.routing_table.get_last_known_sequence(destination),
+			origin: self.address,
+			origin_sequence: next_id(&mut self.current_sequence_number),
+			first_hop: self.address,
+			metric: 0,
+		};
+		
+		self.broadcast(request.to_bytes(), now);
+		true
+	}
+	
+	/// Repeats route requests that weren't answered in time, failing the waiting data once all retries are used up
+	fn check_discoveries(&mut self, now: Instant) {
+		let due_discoveries: Vec<_> = self.discoveries.iter()
+			.filter(|(_, discovery)| now >= discovery.deadline)
+			.map(|(&destination, discovery)| (destination, discovery.requests))
+			.collect();
+		
+		for (destination, requests) in due_discoveries {
+			if requests > self.parameters.rreq_retries {
+				warn!(%destination, "Found no route after {requests} route requests");
+				
+				let queued_ids: Vec<u16> = self.outbound_messages.get(&destination)
+					.map(|queue| queue.iter().map(|queued| queued.id).collect())
+					.unwrap_or_default();
+				
+				// the discovery fails with the last waiting delivery
+				for id in queued_ids {
+					self.fail_delivery((destination, id), DeliveryFailure::NoRoute);
+				}
+				
+				if self.discoveries.remove(&destination).is_some() {
+					self.stats.discoveries_failed += 1;
+					self.emit(AODVEvent::DiscoveryFailed {
+						destination,
+					});
+				}
+				
+				continue;
+			}
+			
+			// tried again on the next maintenance if the rate limit is exceeded
+			if !self.request_route(destination, now) {
+				continue;
+			}
+			
+			let net_traversal_time = self.parameters.net_traversal_time();
+			let discovery = self.discoveries.get_mut(&destination)
+				.expect("discovery was just taken from the map");
+			
+			// binary exponential backoff
+			discovery.deadline = now + net_traversal_time * (1 << requests);
+			discovery.requests += 1;
+		}
+	}
+	
+	/// Removes discovered routes that weren't confirmed or used for data recently
+	fn expire_routes(&mut self, now: Instant) {
+		let expired_routes = self.routing_table.stale_routes(now, self.parameters.active_route_timeout);
+		
+		for (destination, next_hop) in expired_routes {
+			if self.routing_table.remove_route(destination, next_hop) {
+				self.emit(AODVEvent::RouteExpired {
+					destination,
+					next_hop,
+				});
+			}
+		}
+	}
+	
+	/// Sends a DataPacket originating from this node, keeping its delivery status up to date
+	fn transmit_data(&mut self, next_hop: ATAddress, packet: &DataPacket, now: Instant) {
+		let delivery = (packet.destination, packet.id);
+		
+		self.update_delivery(delivery, DeliveryStatus::RouteFound);
+		self.send_unicast_tracked(next_hop, &packet.to_bytes(), Some(delivery), now);
+		
+		// with link-layer acknowledgements the packet only counts as transmitted once acknowledged
+		if self.parameters.link_acks.is_none() {
+			self.update_delivery(delivery, DeliveryStatus::Transmitted);
+		}
+	}
+	
+	fn check_deliveries(&mut self, now: Instant) {
+		let delivery = self.parameters.delivery;
+		
+		self.seen_data.retain(|_, received| now - *received <= delivery.retransmission_window());
+		
+		let due_deliveries: Vec<_> = self.pending_deliveries.iter()
+			.filter(|(_, pending)| now >= pending.deadline)
+			.map(|(&delivery, _)| delivery)
+			.collect();
+		
+		for (destination, id) in due_deliveries {
+			let pending = self.pending_deliveries.get_mut(&(destination, id))
+				.expect("delivery was just taken from the map");
+			
+			if !pending.reliable {
+				// unreliable deliveries that found a route are finished by the link layer
+				if pending.status != DeliveryStatus::Queued {
+					continue;
+				}
+				
+				warn!(%destination, id = format_args!("{id:04X}"), "Found no route to deliver message");
+				self.fail_delivery((destination, id), DeliveryFailure::NoRoute);
+				continue;
+			}
+			
+			if pending.retries >= delivery.max_retries {
+				warn!(%destination, id = format_args!("{id:04X}"), "Delivery of message failed");
+				
+				let failure = if pending.status == DeliveryStatus::Queued {
+					DeliveryFailure::NoRoute
+				} else {
+					DeliveryFailure::Timeout
+				};
+				
+				self.fail_delivery((destination, id), failure);
+				continue;
+			}
+			
+			pending.retries += 1;
+			pending.deadline = now + delivery.timeout;
+			self.stats.delivery_retries += 1;
+			
+			let packet = DataPacket {
+				id,
+				ack_requested: true,
+				hop_count: 0,
+				destination,
+				origin: self.address,
+				payload: pending.payload.clone(),
+			};
+			
+			self.send_data(packet, now);
+		}
+	}
+	
+	fn handle_hello_timer(&mut self, now: Instant) {
+		// the hello was moved to a later point since this timer was scheduled
+		if now < self.next_hello {
+			self.schedule_timer(Timer::Hello, self.next_hello);
+			return;
+		}
+		
+		let interval = if self.hello_replaced(now) {
+			// neighbors keep expecting hellos at the interval they were told last
+			self.advertised_interval
+		} else {
+			let interval = self.hello_schedule.next_interval(mem::take(&mut self.neighborhood_changed));
+			
+			self.send_hello(interval);
+			self.advertised_interval = interval;
+			self.last_hello = now;
+			interval
+		};
+		
+		self.next_hello = now + with_jitter(interval, &mut self.rng);
+		self.schedule_timer(Timer::Hello, self.next_hello);
+	}
+	
+	/// Whether neighbors heard a route request or error recently enough to still be up until the hello after this one
+	fn hello_replaced(&self, now: Instant) -> bool {
+		let Some(last_broadcast) = self.last_broadcast else {
+			return false;
+		};
+		
+		let interval = self.advertised_interval;
+		
+		// neighbors scale the timeout to the interval this node advertised
+		let hello_timeout = interval.mul_f64(self.parameters.hello_timeout.as_secs_f64() / self.parameters.hello_interval.min.as_secs_f64());
+		
+		// the next hello is sent at most one interval from now
+		now - last_broadcast < interval.min(hello_timeout.saturating_sub(interval))
+	}
+	
+	fn send_hello(&mut self, hello_interval: Duration) {
+		let packet = HelloPacket {
+			sequence: next_id(&mut self.current_sequence_number),
+			interval: hello_interval,
+			info: self.node_info.clone(),
+			neighbors: self.routing_table.neighbors().up_neighbors(),
+		};
+		
+		// not a `broadcast`, only other traffic may replace a hello
+		self.actions.push_back(Action::Broadcast {
+			frame: packet.to_bytes(),
+		});
+	}
+	
+	fn check_neighbor_hello(&mut self, now: Instant) {
+		self.routing_table.neighbors_mut()
+			.remove_stale(now);
+		
+		let timed_out_neighbors = self.routing_table.neighbors()
+			.expired(now);
+		
+		for neighbor in timed_out_neighbors {
+			self.break_link(neighbor, true, now);
+		}
+	}
+	
+	fn link_cost(&mut self, neighbor: ATAddress, now: Instant) -> u16 {
+		let link_quality = self.routing_table.neighbors_mut()
+			.link_quality(neighbor, now);
+		
+		self.metric.link_cost(&link_quality)
+	}
+	
+	fn handle_neighbor_event(&mut self, event: Option<NeighborEvent>, now: Instant) {
+		let event = match event {
+			Some(NeighborEvent::Up(neighbor)) => {
+				info!(%neighbor, "Link is up");
+				debug!("Neighbors:\n{}", self.routing_table.neighbors());
+				AODVEvent::NeighborUp(neighbor)
+			},
+			Some(NeighborEvent::Down(neighbor)) => {
+				info!(%neighbor, "Link is down");
+				debug!("Neighbors:\n{}", self.routing_table.neighbors());
+				AODVEvent::NeighborDown(neighbor)
+			},
+			None => return,
+		};
+		
+		self.emit(event);
+		
+		self.neighborhood_changed = true;
+		
+		// a changing neighborhood needs hellos sooner, but never more often than the minimum interval
+		let earliest_hello = (self.last_hello + self.hello_schedule.min_interval()).max(now);
+		
+		if earliest_hello < self.next_hello {
+			self.next_hello = earliest_hello;
+			self.schedule_timer(Timer::Hello, earliest_hello);
+		}
+	}
+	
+	/// Invalidates all routes over `neighbor` and notifies other nodes about it, `timed_out` if it stopped sending hellos
+	fn break_link(&mut self, neighbor: ATAddress, timed_out: bool, now: Instant) {
+		let event = self.routing_table.neighbors_mut()
+			.mark_down(neighbor);
+		
+		self.handle_neighbor_event(event, now);
+		
+		let mut broken_routes: Vec<_> = self.routing_table.routes_with_next_hop(neighbor)
+			.map(|(destination, _)| destination)
+			.collect();
+		
+		// the direct route to the neighbor itself is gone as well
+		if event.is_some() && !broken_routes.contains(&neighbor) {
+			broken_routes.push(neighbor);
+		}
+		
+		for destination in broken_routes {
+			if self.routing_table.remove_route(destination, neighbor) {
+				let event = if timed_out {
+					AODVEvent::RouteExpired {
+						destination,
+						next_hop: neighbor,
+					}
+				} else {
+					AODVEvent::RouteInvalidated {
+						destination,
+						next_hop: neighbor,
+					}
+				};
+				
+				self.emit(event);
+			}
+			
+			// failed over to an alternative route, so the destination is still reachable
+			if self.routing_table.get_route(destination, None).is_some() {
+				continue;
+			}
+			
+			let packet = RouteErrorPacket {
+				destination,
+			};
+			
+			self.broadcast(packet.to_bytes(), now);
+		}
+	}
+	
+	fn broadcast(&mut self, frame: Box<[u8]>, now: Instant) {
+		self.last_broadcast = Some(now);
+		self.actions.push_back(Action::Broadcast {
+			frame,
+		});
+	}
+	
+	/// Sends a packet to a neighbor, requesting a link-layer acknowledgement if enabled
+	fn send_unicast(&mut self, next_hop: ATAddress, packet: &[u8], now: Instant) {
+		self.send_unicast_tracked(next_hop, packet, None, now);
+	}
+	
+	fn send_unicast_tracked(&mut self, next_hop: ATAddress, packet: &[u8], delivery: Option<(ATAddress, u16)>, now: Instant) {
+		let Some(link_acks) = self.parameters.link_acks else {
+			self.actions.push_back(Action::Send {
+				next_hop,
+				frame: packet.into(),
+			});
+			return;
+		};
+		
+		let link_id = next_id(&mut self.current_link_id);
+		let frame = with_link_header(link_id, packet);
+		
+		self.pending_link_acks.insert(link_id, PendingLinkAck {
+			next_hop,
+			delivery,
+			frame: frame.clone(),
+			retries: 0,
+			deadline: now + link_acks.timeout,
+		});
+		
+		self.actions.push_back(Action::Send {
+			next_hop,
+			frame,
+		});
+	}
+	
+	/// Reliable deliveries recover from broken links through end-to-end retransmissions
+	fn fail_unreliable_delivery(&mut self, delivery: (ATAddress, u16)) {
+		let is_reliable = self.pending_deliveries.get(&delivery)
+			.is_some_and(|pending| pending.reliable);
+		
+		if !is_reliable {
+			self.update_delivery(delivery, DeliveryStatus::Failed(DeliveryFailure::LinkBroken));
+		}
+	}
+	
+	fn check_link_acks(&mut self, now: Instant) {
+		// neighbors may request acknowledgements even if this node doesn't
+		let seen_link_frame_timeout = self.parameters.link_acks
+			.map_or(SEEN_LINK_FRAME_TIMEOUT, |link_acks| link_acks.retransmission_window());
+		
+		self.seen_link_frames.retain(|_, received| now - *received <= seen_link_frame_timeout);
+		
+		let Some(link_acks) = self.parameters.link_acks else {
+			return;
+		};
+		
+		let timed_out_ids: Vec<_> = self.pending_link_acks.iter()
+			.filter(|(_, pending)| now >= pending.deadline)
+			.map(|(&link_id, _)| link_id)
+			.collect();
+		
+		let mut broken_links = BTreeSet::new();
+		
+		for link_id in timed_out_ids {
+			let pending = self.pending_link_acks.get_mut(&link_id)
+				.expect("id was just taken from the map");
+			
+			if pending.retries >= link_acks.max_retries {
+				warn!(neighbor = %pending.next_hop, link_id = format_args!("{link_id:04X}"), "Neighbor did not acknowledge frame");
+				broken_links.insert(pending.next_hop);
+				
+				if let Some(delivery) = pending.delivery {
+					self.fail_unreliable_delivery(delivery);
+				}
+				
+				self.pending_link_acks.remove(&link_id);
+				continue;
+			}
+			
+			pending.retries += 1;
+			pending.deadline = now + link_acks.backoff_timeout(pending.retries);
+			self.stats.link_retries += 1;
+			
+			let action = Action::Send {
+				next_hop: pending.next_hop,
+				frame: pending.frame.clone(),
+			};
+			
+			self.actions.push_back(action);
+		}
+		
+		for neighbor in broken_links {
+			let mut failed_deliveries = Vec::new();
+			
+			// frames queued for a broken link will never be acknowledged either
+			self.pending_link_acks.retain(|_, pending| {
+				if pending.next_hop != neighbor {
+					return true;
+				}
+				
+				failed_deliveries.extend(pending.delivery);
+				false
+			});
+			
+			for delivery in failed_deliveries {
+				self.fail_unreliable_delivery(delivery);
+			}
+			
+			self.break_link(neighbor, false, now);
+		}
+	}
+	
+	/// Rebroadcasts a flooded packet after a random delay, unless enough neighbors rebroadcast it first
+	fn schedule_broadcast(&mut self, key: FloodKey, packet: Box<[u8]>, now: Instant) {
+		let is_scheduled = self.pending_broadcasts.schedule(key, packet, now, &mut self.rng);
+		
+		if !is_scheduled {
+			debug!(?key, "Skipped rebroadcast");
+			return;
+		}
+		
+		if let Some(deadline) = self.pending_broadcasts.next_deadline() {
+			self.schedule_timer(Timer::Broadcast, deadline);
+		}
+	}
+	
+	fn send_due_broadcasts(&mut self, now: Instant) {
+		for frame in self.pending_broadcasts.take_due(now) {
+			self.broadcast(frame, now);
+		}
+		
+		if let Some(deadline) = self.pending_broadcasts.next_deadline() {
+			self.schedule_timer(Timer::Broadcast, deadline);
+		}
+	}
+	
+	fn send_outbound_messages(&mut self, destination: ATAddress, route: Route, now: Instant) {
+		if let Some(discovery) = self.discoveries.remove(&destination) {
+			self.stats.discoveries_succeeded += 1;
+			self.stats.discovery_latency += now - discovery.started;
+			
+			self.emit(AODVEvent::DiscoverySucceeded {
+				destination,
+				hop_count: route.hop_count,
+			});
+		}
+		
+		let Some(messages) = self.outbound_messages.get_mut(&destination) else {
+			return;
+		};
+		
+		for packet in mem::take(messages) {
+			self.transmit_data(route.next_hop, &packet, now);
+		}
+	}
+	
+	/// Reports a route learned from a route request or reply, `is_known` if there already was a route over the same next hop
+	fn report_route(&mut self, destination: ATAddress, route: Route, is_known: bool) {
+		let Route { next_hop, hop_count, metric, .. } = route;
+		
+		let event = if is_known {
+			AODVEvent::RouteUpdated {
+				destination,
+				next_hop,
+				hop_count,
+				metric,
+			}
+		} else {
+			AODVEvent::RouteAdded {
+				destination,
+				next_hop,
+				hop_count,
+				metric,
+			}
+		};
+		
+		self.emit(event);
+	}
+	
+	fn drop_packet(&mut self, sender: ATAddress, reason: DropReason) {
+		count(&mut self.stats.packets_dropped, reason);
+		
+		self.emit(AODVEvent::PacketDropped {
+			sender,
+			reason,
+		});
+	}
+	
+	fn update_sequence_number(&mut self, new_sequence_number: u16) {
+		if sequence_number_newer(new_sequence_number, self.current_sequence_number) {
+			self.current_sequence_number = new_sequence_number;
+		}
+	}
+	
+	pub fn handle_frame(&mut self, message: &ATMessage, now: Instant) {
+		if !self.neighbor_filter.allows(message.address) {
+			self.drop_packet(message.address, DropReason::Filtered);
+			return;
+		}
+		
+		let packet = match parse_packet(message) {
+			Ok(packet) => packet,
+			Err(err) => {
+				error!(sender = %message.address, %err, "Encountered invalid packet:\n\t{message}");
+				self.drop_packet(message.address, DropReason::Invalid);
+				return;
+			},
+		};
+		
+		self.handle_packet(&packet, now);
+	}
+	
+	fn handle_packet(&mut self, packet: &AODVPacket, now: Instant) {
+		use AODVPacketBody::*;
+		
+		let sender = packet.sender;
+		
+		count(&mut self.stats.frames_received, packet.body.packet_type());
+		
+		let is_hello = matches!(packet.body, Hello(_));
+		let event = self.routing_table.neighbors_mut()
+			.record_frame(sender, packet.signal, is_hello, now);
+		
+		self.handle_neighbor_event(event, now);
+		
+		if let Some(link_id) = packet.link_id {
+			let is_new_frame = self.acknowledge_link_frame(sender, link_id, now);
+			
+			if !is_new_frame {
+				// retransmission of a frame whose acknowledgement got lost
+				return;
+			}
+		}
+		
+		match &packet.body {
+			RouteRequest(packet) => self.handle_route_request(sender, packet, now),
+			RouteReply(packet) => self.handle_route_reply(sender, packet, now),
+			RouteError(packet) => self.handle_route_error(sender, packet, now),
+			Data(data) => self.handle_data(sender, data, packet.signal, now),
+			LinkAck(packet) => self.handle_link_ack(sender, packet),
+			DataAck(packet) => self.handle_data_ack(sender, packet, now),
+			Hello(packet) => self.handle_hello(sender, packet, now),
+		}
+	}
+	
+	/// Returns whether the frame was received for the first time
+	fn acknowledge_link_frame(&mut self, sender: ATAddress, link_id: u16, now: Instant) -> bool {
+		let ack = LinkAckPacket {
+			link_id,
+		};
+		
+		self.actions.push_back(Action::Send {
+			next_hop: sender,
+			frame: ack.to_bytes(),
+		});
+		
+		self.seen_link_frames.insert((sender, link_id), now)
+			.is_none()
+	}
+	
+	fn handle_link_ack(&mut self, sender: ATAddress, packet: &LinkAckPacket) {
+		let is_expected = self.pending_link_acks.get(&packet.link_id)
+			.is_some_and(|pending| pending.next_hop == sender);
+		
+		if !is_expected {
+			return;
+		}
+		
+		let pending = self.pending_link_acks.remove(&packet.link_id)
+			.expect("presence was just checked");
+		
+		if let Some(delivery) = pending.delivery {
+			self.update_delivery(delivery, DeliveryStatus::Transmitted);
+		}
+	}
+	
+	fn handle_route_request(&mut self, sender: ATAddress, packet: &RouteRequestPacket, now: Instant) {
+		if packet.origin == self.address {
+			return;
+		}
+		
+		let request_key = (packet.origin, packet.id);
+		let is_new_request = !self.seen_requests.contains_key(&request_key);
+		let flood_key = FloodKey::RouteRequest {
+			origin: packet.origin,
+			id: packet.id,
+		};
+		
+		match self.request_limiter.check_neighbor(sender, is_new_request, now) {
+			Throttling::None => (),
+			Throttling::Started => {
+				warn!(%sender, "Throttling neighbor for sending too many route requests");
+				self.drop_packet(sender, DropReason::RateLimited);
+				return;
+			},
+			Throttling::Ongoing => {
+				self.drop_packet(sender, DropReason::RateLimited);
+				return;
+			},
+		}
+		
+		if !is_new_request {
+			self.pending_broadcasts.overheard(flood_key);
+		}
+		
+		let reply_sequence = *self.seen_requests.entry(request_key)
+			.or_default();
+		
+		self.update_sequence_number(packet.origin_sequence);
+		
+		let metric = packet.metric.saturating_add(self.link_cost(sender, now));
+		
+		let first_hop = if packet.hop_count == 0 {
+			self.address
+		} else {
+			packet.first_hop
+		};
+		
+		let is_known = self.routing_table.has_route(packet.origin, sender);
+		
+		// copies of a request arriving over different paths provide alternative routes back to the origin
+		let new_route = self.routing_table.add_route(packet.origin, packet.origin_sequence, sender, packet.hop_count.saturating_add(1), metric, Some(first_hop), now);
+		
+		if let Some(new_route) = new_route {
+			self.report_route(packet.origin, new_route, is_known);
+			self.send_outbound_messages(packet.origin, new_route, now);
+		}
+		
+		// the destination answers every copy that added a route, so the origin learns alternative routes as well
+		if !is_new_request && (packet.destination != self.address || new_route.is_none()) {
+			return;
+		}
+		
+		if let Some(route) = self.routing_table.get_route(packet.destination, packet.destination_sequence) {
+			let sequence = if packet.destination == self.address {
+				// all replies to a request need the same sequence number to count as alternatives
+				match reply_sequence {
+					Some(sequence) => sequence,
+					None => {
+						let sequence = next_id(&mut self.current_sequence_number);
+						self.seen_requests.insert(request_key, Some(sequence));
+						sequence
+					},
+				}
+			} else {
+				route.destination_sequence
+			};
+			
+			// the node the reply is sent to is the destination's neighbor if this node is the destination
+			let last_hop = if packet.destination == self.address {
+				Some(sender)
+			} else {
+				route.last_hop
+			};
+			
+			let reply = RouteReplyPacket {
+				hop_count: route.hop_count,
+				metric: route.metric,
+				request_destination: packet.destination,
+				request_destination_sequence: sequence,
+				request_origin: packet.origin,
+				last_hop,
+			};
+			
+			self.send_unicast(sender, &reply.to_bytes(), now);
+			return;
+		}
+		
+		if packet.hop_count.saturating_add(1) >= self.parameters.net_diameter {
+			debug!(%sender, packet_type = "RREQ", origin = %packet.origin, id = format_args!("{:04X}", packet.id), "Not forwarding route request, it reached the net diameter");
+			return;
+		}
+		
+		if let Err(limit) = self.request_limiter.allow_forward(packet.origin, now) {
+			warn!(%sender, packet_type = "RREQ", origin = %packet.origin, id = format_args!("{:04X}", packet.id), ?limit, "Not forwarding route request, rate limit exceeded");
+			self.drop_packet(sender, DropReason::RateLimited);
+			return;
+		}
+		
+		let packet = RouteRequestPacket {
+			hop_count: packet.hop_count.saturating_add(1),
+			metric,
+			first_hop,
+			..*packet
+		};
+		
+		self.schedule_broadcast(flood_key, packet.to_bytes(), now);
+	}
+	
+	fn handle_route_reply(&mut self, sender: ATAddress, packet: &RouteReplyPacket, now: Instant) {
+		self.update_sequence_number(packet.request_destination_sequence);
+		
+		let metric = packet.metric.saturating_add(self.link_cost(sender, now));
+		
+		let last_hop = if packet.hop_count == 0 {
+			Some(self.address)
+		} else {
+			packet.last_hop
+		};
+		
+		let is_known = self.routing_table.has_route(packet.request_destination, sender);
+		
+		if let Some(new_route) = self.routing_table.add_route(packet.request_destination, packet.request_destination_sequence, sender, packet.hop_count.saturating_add(1), metric, last_hop, now) {
+			self.report_route(packet.request_destination, new_route, is_known);
+			self.send_outbound_messages(packet.request_destination, new_route, now);
+		}
+		
+		let request_origin = packet.request_origin;
+		
+		// RouteReplyPackets for self don't need to be forwarded
+		if request_origin == self.address {
+			return;
+		}
+		
+		let Some(route) = self.routing_table.get_route(request_origin, None) else {
+			warn!(%sender, packet_type = "RREP", origin = %packet.request_origin, "No route to the origin of a route reply");
+			self.drop_packet(sender, DropReason::NoRoute);
+			
+			let packet = RouteErrorPacket {
+				destination: request_origin,
+			};
+			
+			self.broadcast(packet.to_bytes(), now);
+			return;
+		};
+		
+		let packet = RouteReplyPacket {
+			hop_count: packet.hop_count.saturating_add(1),
+			metric,
+			..*packet
+		};
+		
+		self.send_unicast(route.next_hop, &packet.to_bytes(), now);
+	}
+	
+	fn handle_hello(&mut self, sender: ATAddress, packet: &HelloPacket, now: Instant) {
+		self.update_sequence_number(packet.sequence);
+		
+		let link_cost = self.link_cost(sender, now);
+		
+		let event = self.routing_table.neighbors_mut()
+			.record_hello(sender, packet, link_cost, now);
+		
+		self.handle_neighbor_event(event, now);
+		
+		if let Some(route) = self.routing_table.get_route(sender, None) {
+			self.send_outbound_messages(sender, route, now);
+		}
+	}
+	
+	fn handle_route_error(&mut self, sender: ATAddress, packet: &RouteErrorPacket, now: Instant) {
+		if packet.destination == sender {
+			// the neighbor is shutting down
+			self.break_link(sender, false, now);
+			return;
+		}
+		
+		let flood_key = FloodKey::RouteError {
+			destination: packet.destination,
+		};
+		
+		self.pending_broadcasts.overheard(flood_key);
+		
+		let is_route_removed = self.routing_table.remove_route(packet.destination, sender);
+		
+		if !is_route_removed {
+			// no changes were made, so no need to notify others
+			return;
+		}
+		
+		self.emit(AODVEvent::RouteInvalidated {
+			destination: packet.destination,
+			next_hop: sender,
+		});
+		
+		if self.routing_table.get_route(packet.destination, None).is_some() {
+			// failed over to an alternative route, others can still reach the destination through this node
+			return;
+		}
+		
+		self.schedule_broadcast(flood_key, packet.to_bytes(), now);
+	}
+	
+	fn handle_data(&mut self, sender: ATAddress, packet: &DataPacket, signal: Option<ATSignal>, now: Instant) {
+		// data keeps the route back to its origin active
+		self.routing_table.refresh_route(packet.origin, sender, now);
+		
+		if packet.destination == self.address {
+			if packet.ack_requested {
+				self.acknowledge_data(packet, now);
+			}
+			
+			let is_new_message = self.seen_data.insert((packet.origin, packet.id), now)
+				.is_none();
+			
+			// the origin retransmits if our acknowledgement got lost
+			if is_new_message {
+				self.stats.data_delivered += 1;
+				self.actions.push_back(Action::Deliver(ReceivedMessage {
+					origin: packet.origin,
+					payload: packet.payload.clone(),
+					hop_count: packet.hop_count.saturating_add(1),
+					received: now,
+					signal,
+				}));
+			}
+			
+			return;
+		}
+		
+		let Some(route) = self.routing_table.get_route(packet.destination, None) else {
+			warn!(%sender, packet_type = "DATA", destination = %packet.destination, "No route to the destination of data");
+			self.drop_packet(sender, DropReason::NoRoute);
+			
+			let packet = RouteErrorPacket {
+				destination: packet.destination,
+			};
+			
+			self.broadcast(packet.to_bytes(), now);
+			return;
+		};
+		
+		self.stats.data_forwarded += 1;
+		self.routing_table.refresh_route(packet.destination, route.next_hop, now);
+		
+		let packet = DataPacket {
+			hop_count: packet.hop_count.saturating_add(1),
+			payload: packet.payload.clone(),
+			..*packet
+		};
+		
+		self.send_unicast(route.next_hop, &packet.to_bytes(), now);
+	}
+	
+	fn acknowledge_data(&mut self, packet: &DataPacket, now: Instant) {
+		let Some(route) = self.routing_table.get_route(packet.origin, None) else {
+			// the origin will retransmit, possibly finding a new route
+			warn!(packet_type = "DATA", origin = %packet.origin, "Cannot acknowledge data without a route to its origin");
+			return;
+		};
+		
+		let ack = DataAckPacket {
+			id: packet.id,
+			destination: packet.origin,
+			origin: self.address,
+		};
+		
+		self.send_unicast(route.next_hop, &ack.to_bytes(), now);
+	}
+	
+	fn handle_data_ack(&mut self, sender: ATAddress, packet: &DataAckPacket, now: Instant) {
+		if packet.destination == self.address {
+			let delivery = (packet.origin, packet.id);
+			
+			// acknowledgements of retransmissions may arrive more than once
+			if self.pending_deliveries.contains_key(&delivery) {
+				info!(destination = %packet.origin, id = format_args!("{:04X}", packet.id), "Message delivered");
+				self.update_delivery(delivery, DeliveryStatus::Acknowledged);
+			}
+			
+			return;
+		}
+		
+		let Some(route) = self.routing_table.get_route(packet.destination, None) else {
+			warn!(%sender, packet_type = "DACK", destination = %packet.destination, "No route to the destination of a data acknowledgement");
+			self.drop_packet(sender, DropReason::NoRoute);
+			
+			let packet = RouteErrorPacket {
+				destination: packet.destination,
+			};
+			
+			self.broadcast(packet.to_bytes(), now);
+			return;
+		};
+		
+		self.send_unicast(route.next_hop, &packet.to_bytes(), now);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::aodv::{
+		metric::{HopCount, LINK_COST_UNIT},
+		flooding::{BroadcastSuppression, FloodingConfig},
+		hello_schedule::HelloIntervalConfig,
+		neighbor_table::LinkState,
+		rate_limit::RouteRequestLimits,
+		DeliveryConfig,
+		LinkAckConfig,
+	};
+	
+	use super::*;
+	
+	const DELIVERY: DeliveryConfig = DeliveryConfig {
+		timeout: Duration::from_secs(2),
+		max_retries: 2,
+	};
+	
+	const LINK_ACKS: LinkAckConfig = LinkAckConfig {
+		timeout: Duration::from_millis(200),
+		max_retries: 2,
+	};
+	
+	/// Nodes in a line, each only hearing its direct predecessor and successor, with instant transmissions
+	struct Network {
+		nodes: Vec<AODVProtocol>,
+		links: BTreeSet<(usize, usize)>,
+		timers: BTreeMap<(usize, Timer), Instant>,
+		received: Vec<(usize, ReceivedMessage)>,
+		events: Vec<(usize, AODVEvent)>,
+		deliveries: BTreeMap<(usize, u16), DeliveryStatus>,
+		next_data_id: u16,
+		now: Instant,
+	}
+	
+	impl Network {
+		fn line(length: usize, link_acks: Option<LinkAckConfig>) -> Self {
+			let now = Instant::now();
+			
+			let nodes = (0..length)
+				.map(|index| {
+					let address = ATAddress::new(format!("{:04}", index + 1).into_bytes().try_into().unwrap()).unwrap();
+					
+					let parameters = AODVParameters {
+						hello_interval: HelloIntervalConfig {
+							min: Duration::from_secs(10),
+							max: Duration::from_secs(60),
+						},
+						hello_timeout: Duration::from_secs(25),
+						active_route_timeout: Duration::from_secs(5),
+						net_diameter: 5,
+						node_traversal_time: Duration::from_millis(20),
+						rreq_retries: 2,
+						link_acks,
+						delivery: DELIVERY,
+						flooding: FloodingConfig {
+							max_jitter: Duration::from_millis(100),
+							suppression: BroadcastSuppression::None,
+						},
+						route_request_limits: RouteRequestLimits::default(),
+						max_queued_messages: 8,
+					};
+					
+					let mut protocol = AODVProtocol::new(address, parameters, Box::new(HopCount), NodeInfo::default(), now).unwrap();
+					
+					protocol.seed_rng(index as u64);
+					protocol
+				})
+				.collect();
+			
+			let mut network = Self {
+				nodes,
+				links: (1..length).map(|index| (index - 1, index)).collect(),
+				timers: BTreeMap::new(),
+				received: Vec::new(),
+				events: Vec::new(),
+				deliveries: BTreeMap::new(),
+				next_data_id: 0,
+				now,
+			};
+			
+			network.process();
+			network
+		}
+		
+		fn address(&self, index: usize) -> ATAddress {
+			self.nodes[index].address()
+		}
+		
+		fn is_linked(&self, a: usize, b: usize) -> bool {
+			self.links.contains(&(a.min(b), a.max(b)))
+		}
+		
+		/// Performs the actions of all nodes until no frames are in flight
+		fn process(&mut self) {
+			let mut frames = VecDeque::new();
+			
+			loop {
+				for index in 0..self.nodes.len() {
+					while let Some(action) = self.nodes[index].poll_action() {
+						match action {
+							Action::Send { next_hop, frame } => {
+								let receivers = (0..self.nodes.len())
+									.filter(|&receiver| self.address(receiver) == next_hop && self.is_linked(index, receiver));
+								
+								frames.extend(receivers.map(|receiver| (index, receiver, frame.clone())));
+							},
+							Action::Broadcast { frame } => {
+								let receivers = (0..self.nodes.len())
+									.filter(|&receiver| self.is_linked(index, receiver));
+								
+								frames.extend(receivers.map(|receiver| (index, receiver, frame.clone())));
+							},
+							Action::Deliver(message) => self.received.push((index, message)),
+							Action::Event(event) => self.events.push((index, event)),
+							Action::ScheduleTimer { timer, at } => {
+								self.timers.insert((index, timer), at);
+							},
+							Action::UpdateDelivery { id, status, .. } => {
+								self.deliveries.insert((index, id), status);
+							},
+						}
+					}
+				}
+				
+				let Some((sender, receiver, data)) = frames.pop_front() else {
+					return;
+				};
+				
+				let message = ATMessage {
+					address: self.address(sender),
+					data,
+					signal: None,
+				};
+				
+				self.nodes[receiver].handle_frame(&message, self.now);
+			}
+		}
+		
+		/// Fires all timers that are due within `duration`
+		fn advance(&mut self, duration: Duration) {
+			let end = self.now + duration;
+			
+			loop {
+				let next_timer = self.timers.iter()
+					.min_by_key(|(_, at)| **at)
+					.map(|(&key, &at)| (key, at))
+					.filter(|(_, at)| *at <= end);
+				
+				let Some(((index, timer), at)) = next_timer else {
+					break;
+				};
+				
+				self.timers.remove(&(index, timer));
+				self.now = self.now.max(at);
+				self.nodes[index].handle_timer(timer, self.now);
+				self.process();
+			}
+			
+			self.now = end;
+		}
+		
+		fn send(&mut self, from: usize, to: usize, payload: &[u8], reliable: bool) -> u16 {
+			let destination = self.address(to);
+			let id = next_id(&mut self.next_data_id);
+			
+			self.nodes[from].send(destination, id, payload.into(), reliable, self.now);
+			
+			self.process();
+			id
+		}
+	}
+	
+	#[test]
+	fn delivers_over_discovered_route() {
+		let mut network = Network::line(3, None);
+		
+		network.advance(Duration::from_secs(1));
+		
+		let id = network.send(0, 2, b"hello", false);
+		network.advance(Duration::from_secs(1));
+		
+		let (receiver, message) = &network.received[0];
+		
+		assert_eq!(network.received.len(), 1);
+		assert_eq!(*receiver, 2);
+		assert_eq!((message.origin, &*message.payload, message.hop_count), (network.address(0), &b"hello"[..], 2));
+		assert_eq!(network.deliveries[&(0, id)], DeliveryStatus::Transmitted);
+	}
+	
+	#[test]
+	fn reports_discovery_and_routes() {
+		let mut network = Network::line(3, None);
+		
+		network.advance(Duration::from_secs(1));
+		network.events.clear();
+		
+		network.send(0, 2, b"hello", false);
+		
+		let destination = network.address(2);
+		let events: Vec<_> = network.events.iter()
+			.filter(|(index, _)| *index == 0)
+			.map(|(_, event)| event.clone())
+			.collect();
+		
+		assert_eq!(events, [
+			AODVEvent::DiscoveryStarted { destination },
+			AODVEvent::RouteAdded { destination, next_hop: network.address(1), hop_count: 2, metric: 2 * LINK_COST_UNIT },
+			AODVEvent::DiscoverySucceeded { destination, hop_count: 2 },
+		]);
+	}
+	
+	#[test]
+	fn counts_data_and_discoveries() {
+		let mut network = Network::line(3, None);
+		
+		network.advance(Duration::from_secs(1));
+		network.send(0, 2, b"hello", false);
+		
+		let stats = network.nodes[0].reset_stats(network.now);
+		
+		assert_eq!((stats.data_originated, stats.discoveries_started, stats.discoveries_succeeded), (1, 1, 1));
+		assert_eq!(stats.frames_sent[&PacketType::RouteRequest], 1);
+		assert_eq!(stats.frames_sent[&PacketType::Data], 1);
+		assert_eq!(network.nodes[1].stats().data_forwarded, 1);
+		assert_eq!(network.nodes[2].stats().data_delivered, 1);
+		
+		assert_eq!(network.nodes[0].stats().data_originated, 0);
+		assert!(network.nodes[0].stats().frames_sent.is_empty());
+	}
+	
+	#[test]
+	fn acknowledges_reliable_delivery() {
+		let mut network = Network::line(2, Some(LINK_ACKS));
+		
+		network.advance(Duration::from_secs(1));
+		
+		let id = network.send(0, 1, b"hello", true);
+		network.advance(Duration::from_secs(1));
+		
+		assert_eq!(network.received.len(), 1);
+		assert_eq!(network.deliveries[&(0, id)], DeliveryStatus::Acknowledged);
+	}
+	
+	#[test]
+	fn fails_without_route() {
+		let mut network = Network::line(2, None);
+		
+		let unknown = ATAddress::new(*b"0099").unwrap();
+		network.nodes[0].send(unknown, 0, b"hello"[..].into(), false, network.now);
+		
+		network.process();
+		network.advance(DELIVERY.timeout);
+		
+		assert_eq!(network.deliveries[&(0, 0)], DeliveryStatus::Failed(DeliveryFailure::NoRoute));
+		assert!(network.events.contains(&(0, AODVEvent::DiscoveryFailed { destination: unknown })));
+	}
+	
+	#[test]
+	fn retries_route_requests() {
+		let mut network = Network::line(2, None);
+		
+		let unknown = ATAddress::new(*b"0099").unwrap();
+		network.nodes[0].send(unknown, 0, b"hello"[..].into(), true, network.now);
+		
+		network.process();
+		network.advance(Duration::from_secs(10));
+		
+		// the first request and two retries, after one and two more net traversal times
+		assert_eq!(network.nodes[0].stats().frames_sent[&PacketType::RouteRequest], 3);
+		assert_eq!(network.deliveries[&(0, 0)], DeliveryStatus::Failed(DeliveryFailure::NoRoute));
+		
+		let failed_discoveries = network.events.iter()
+			.filter(|(_, event)| matches!(event, AODVEvent::DiscoveryFailed { .. }))
+			.count();
+		assert_eq!(failed_discoveries, 1);
+	}
+	
+	#[test]
+	fn limits_queued_messages() {
+		let mut network = Network::line(2, None);
+		
+		let unknown = ATAddress::new(*b"0099").unwrap();
+		
+		for id in 0..9 {
+			network.nodes[0].send(unknown, id, b"hello"[..].into(), false, network.now);
+		}
+		
+		network.process();
+		
+		assert_eq!(network.nodes[0].queue_depths().outbound_messages, 8);
+		assert_eq!(network.deliveries[&(0, 8)], DeliveryStatus::Failed(DeliveryFailure::QueueFull));
+	}
+	
+	#[test]
+	fn expires_unused_routes() {
+		let mut network = Network::line(3, None);
+		let expired = (0, AODVEvent::RouteExpired {
+			destination: network.address(2),
+			next_hop: network.address(1),
+		});
+		
+		network.advance(Duration::from_secs(1));
+		network.send(0, 2, b"hello", false);
+		network.advance(Duration::from_secs(3));
+		
+		// data keeps the route active
+		network.send(0, 2, b"hello", false);
+		network.advance(Duration::from_secs(3));
+		assert!(!network.events.contains(&expired));
+		
+		network.advance(Duration::from_secs(3));
+		assert!(network.events.contains(&expired));
+	}
+	
+	#[test]
+	fn stable_neighbors_stay_up() {
+		let mut network = Network::line(2, None);
+		let unknown = ATAddress::new(*b"0099").unwrap();
+		
+		for id in 0..20 {
+			// failing discoveries broadcast route requests in between hellos
+			if id % 4 == 0 {
+				network.nodes[0].send(unknown, id, b"hello"[..].into(), false, network.now);
+				network.process();
+			}
+			
+			network.advance(Duration::from_secs(30));
+		}
+		
+		assert!(!network.events.iter().any(|(_, event)| matches!(event, AODVEvent::NeighborDown(_))));
+		
+		let now = network.now;
+		assert_eq!(network.nodes[0].neighbor_stats(now)[0].state, LinkState::Up);
+	}
+	
+	#[test]
+	fn forgets_link_frames_without_own_link_acks() {
+		let mut network = Network::line(2, Some(LINK_ACKS));
+		network.nodes[1].parameters.link_acks = None;
+		
+		network.advance(Duration::from_secs(1));
+		network.send(0, 1, b"hello", false);
+		assert!(!network.nodes[1].seen_link_frames.is_empty());
+		
+		network.advance(SEEN_LINK_FRAME_TIMEOUT * 2);
+		assert!(network.nodes[1].seen_link_frames.is_empty());
+		assert_eq!(network.received.len(), 1);
+	}
+	
+	#[test]
+	fn link_ack_backoff_is_capped() {
+		assert_eq!(LINK_ACKS.retransmission_window(), Duration::from_millis(1400));
+		
+		let link_acks = LinkAckConfig {
+			timeout: Duration::from_secs(1),
+			max_retries: u8::MAX,
+		};
+		
+		assert_eq!(link_acks.backoff_timeout(200), Duration::from_secs(64));
+		assert!(link_acks.retransmission_window() < Duration::from_secs(64 * 256));
+	}
+	
+	#[test]
+	fn saturates_hop_counts_from_the_wire() {
+		let mut network = Network::line(3, None);
+		
+		network.advance(Duration::from_secs(1));
+		
+		let request = RouteRequestPacket {
+			hop_count: u8::MAX,
+			metric: 0,
+			id: 1,
+			destination: network.address(1),
+			destination_sequence: None,
+			origin: network.address(0),
+			origin_sequence: 1,
+			first_hop: network.address(1),
+		};
+		
+		let reply = RouteReplyPacket {
+			hop_count: u8::MAX,
+			metric: 0,
+			request_destination: network.address(2),
+			request_destination_sequence: 1,
+			request_origin: network.address(0),
+			last_hop: None,
+		};
+		
+		for (sender, data) in [(0, request.to_bytes()), (2, reply.to_bytes())] {
+			let message = ATMessage {
+				address: network.address(sender),
+				data,
+				signal: None,
+			};
+			
+			let now = network.now;
+			network.nodes[1].handle_frame(&message, now);
+			network.process();
+		}
+		
+		// the saturated routes are stored next to the neighbor routes learned from hellos
+		let now = network.now;
+		let snapshot = network.nodes[1].routing_snapshot(now);
+		
+		for neighbor in [0, 2] {
+			let entry = snapshot.entries.iter()
+				.find(|entry| entry.destination == network.address(neighbor))
+				.unwrap();
+			
+			let routes: BTreeSet<_> = entry.routes.iter()
+				.map(|route| (route.next_hop, route.hop_count))
+				.collect();
+			
+			assert_eq!(routes, BTreeSet::from([(network.address(neighbor), 1), (network.address(neighbor), u8::MAX)]));
+		}
+		
+		assert_eq!(snapshot.entries.len(), 2);
+	}
+	
+	#[test]
+	fn fails_after_link_breaks() {
+		let mut network = Network::line(3, Some(LINK_ACKS));
+		
+		network.advance(Duration::from_secs(1));
+		network.send(0, 2, b"first", true);
+		network.advance(Duration::from_secs(1));
+		
+		network.links.remove(&(1, 2));
+		
+		let id = network.send(0, 2, b"second", true);
+		network.advance(DELIVERY.timeout * (DELIVERY.max_retries as u32 + 1));
+		
+		assert_eq!(network.received.len(), 1);
+		assert!(matches!(network.deliveries[&(0, id)], DeliveryStatus::Failed(_)));
+	}
+	
+	#[test]
+	fn goodbye_takes_link_down() {
+		let mut network = Network::line(3, None);
+		
+		network.advance(Duration::from_secs(1));
+		
+		let now = network.now;
+		network.nodes[1].say_goodbye(now);
+		network.process();
+		
+		for index in [0, 2] {
+			let stats = network.nodes[index].neighbor_stats(now);
+			
+			assert_eq!(stats.len(), 1);
+			assert_eq!(stats[0].state, LinkState::Down);
+			assert!(network.events.contains(&(index, AODVEvent::NeighborDown(network.address(1)))));
+		}
+	}
+	
+	#[test]
+	fn keeps_link_disjoint_replies() {
+		let mut network = Network::line(1, None);
+		let destination = ATAddress::new(*b"0009").unwrap();
+		let now = network.now;
+		
+		// the first two replies reached the destination over the same neighbor of it
+		for (sender, last_hop) in [(b"0005", b"0007"), (b"0006", b"0007"), (b"0008", b"0004")] {
+			let reply = RouteReplyPacket {
+				hop_count: 2,
+				metric: 0,
+				request_destination: destination,
+				request_destination_sequence: 1,
+				request_origin: network.address(0),
+				last_hop: Some(ATAddress::new(*last_hop).unwrap()),
+			};
+			
+			let message = ATMessage {
+				address: ATAddress::new(*sender).unwrap(),
+				data: reply.to_bytes(),
+				signal: None,
+			};
+			
+			network.nodes[0].handle_frame(&message, now);
+		}
+		
+		let snapshot = network.nodes[0].routing_snapshot(now);
+		let entry = snapshot.entries.iter()
+			.find(|entry| entry.destination == destination)
+			.unwrap();
+		
+		let routes: BTreeSet<_> = entry.routes.iter()
+			.map(|route| (route.next_hop, route.last_hop))
+			.collect();
+		
+		assert_eq!(routes, BTreeSet::from([
+			(ATAddress::new(*b"0005").unwrap(), Some(ATAddress::new(*b"0007").unwrap())),
+			(ATAddress::new(*b"0008").unwrap(), Some(ATAddress::new(*b"0004").unwrap())),
+		]));
+	}
+	
+	#[test]
+	fn pinned_route_answers_requests_and_survives_timeouts() {
+		let mut network = Network::line(4, None);
+		
+		network.advance(Duration::from_secs(1));
+		
+		let destination = network.address(3);
+		let route = StaticRoute {
+			next_hop: network.address(2),
+			hop_count: 2,
+			metric: 2 * LINK_COST_UNIT,
+		};
+		
+		let own_address = network.address(1);
+		
+		assert!(network.nodes[1].pin_route(destination, route, network.now));
+		assert!(!network.nodes[1].pin_route(own_address, route, network.now));
+		
+		network.send(0, 3, b"hello", false);
+		
+		// the pinned route answered the request, so it never reached the destination
+		assert_eq!(network.nodes[2].stats().frames_received.get(&PacketType::RouteRequest), None);
+		assert_eq!(network.received.len(), 1);
+		
+		let (_, message) = &network.received[0];
+		assert_eq!(message.hop_count, 3);
+		
+		// the next hop stops sending hellos
+		network.links.remove(&(1, 2));
+		network.advance(Duration::from_secs(60));
+		
+		assert_eq!(network.nodes[1].routing_snapshot(network.now).entries.iter()
+			.find(|entry| entry.destination == destination)
+			.map(|entry| entry.routes[0].pinned), Some(true));
+		
+		assert_eq!(network.nodes[1].unpin_route(destination, network.now), Some(route));
+		assert!(network.nodes[1].static_routes().is_empty());
+	}
+	
+	#[test]
+	fn filter_emulates_topology() {
+		let mut network = Network::line(3, None);
+		
+		// all nodes hear each other, as on a bench
+		network.links.insert((0, 2));
+		network.advance(Duration::from_secs(1));
+		
+		let neighbor_count = network.nodes[0].neighbor_stats(network.now).len();
+		assert_eq!(neighbor_count, 2);
+		
+		let filter = NeighborFilter::Deny(BTreeSet::from([network.address(2)]));
+		network.nodes[0].set_neighbor_filter(filter, network.now);
+		
+		let filter = NeighborFilter::Allow(BTreeSet::from([network.address(1)]));
+		network.nodes[2].set_neighbor_filter(filter, network.now);
+		
+		network.process();
+		network.events.clear();
+		
+		network.send(0, 2, b"hello", false);
+		network.advance(Duration::from_secs(1));
+		
+		let (_, message) = &network.received[0];
+		assert_eq!(message.hop_count, 2);
+		
+		assert!(network.events.contains(&(2, AODVEvent::PacketDropped {
+			sender: network.address(0),
+			reason: DropReason::Filtered,
+		})));
+	}
+}
\ No newline at end of file
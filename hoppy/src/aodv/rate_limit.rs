@@ -0,0 +1,251 @@
+use std::{collections::{BTreeMap, VecDeque}, time::{Duration, Instant}};
+
+use crate::at_module::at_address::ATAddress;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+	pub max_requests: u32,
+	pub window: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RouteRequestLimits {
+	/// Route requests originating from this node
+	pub own: RateLimit,
+	/// Forwarded route requests from a single origin
+	pub per_origin: RateLimit,
+	/// All forwarded route requests
+	pub global: RateLimit,
+	/// New route requests received from a single neighbor, exceeding it gets the neighbor throttled
+	pub per_neighbor: RateLimit,
+	/// How long all route requests from a throttled neighbor are dropped
+	pub throttle_time: Duration,
+}
+
+impl Default for RouteRequestLimits {
+	/// RREQ_RATELIMIT from RFC 3561 for all route requests, with some room for neighbors forwarding for several origins
+	fn default() -> Self {
+		let rate_limit = RateLimit {
+			max_requests: 10,
+			window: Duration::from_secs(1),
+		};
+		
+		Self {
+			own: rate_limit,
+			per_origin: rate_limit,
+			global: rate_limit,
+			per_neighbor: RateLimit {
+				max_requests: 20,
+				..rate_limit
+			},
+			throttle_time: Duration::from_secs(60),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimited {
+	Origin,
+	Global,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttling {
+	/// The neighbor is within its limit
+	None,
+	/// The neighbor exceeded its limit with this request
+	Started,
+	/// The neighbor is still throttled for exceeding its limit earlier
+	Ongoing,
+}
+
+#[derive(Default)]
+struct Window {
+	requests: VecDeque<Instant>,
+}
+
+impl Window {
+	fn prune(&mut self, limit: RateLimit, now: Instant) {
+		while self.requests.front().is_some_and(|&request| now - request >= limit.window) {
+			self.requests.pop_front();
+		}
+	}
+	
+	fn is_full(&mut self, limit: RateLimit, now: Instant) -> bool {
+		self.prune(limit, now);
+		self.requests.len() >= limit.max_requests as usize
+	}
+	
+	/// Records the request if the limit allows it
+	fn try_record(&mut self, limit: RateLimit, now: Instant) -> bool {
+		if self.is_full(limit, now) {
+			return false;
+		}
+		
+		self.requests.push_back(now);
+		true
+	}
+}
+
+pub struct RouteRequestLimiter {
+	limits: RouteRequestLimits,
+	own: Window,
+	global: Window,
+	origins: BTreeMap<ATAddress, Window>,
+	neighbors: BTreeMap<ATAddress, Window>,
+	throttled: BTreeMap<ATAddress, Instant>,
+}
+
+impl RouteRequestLimiter {
+	pub fn new(limits: RouteRequestLimits) -> Self {
+		Self {
+			limits,
+			own: Window::default(),
+			global: Window::default(),
+			origins: BTreeMap::new(),
+			neighbors: BTreeMap::new(),
+			throttled: BTreeMap::new(),
+		}
+	}
+	
+	/// Returns whether this node may send another route request
+	pub fn allow_own(&mut self, now: Instant) -> bool {
+		self.own.try_record(self.limits.own, now)
+	}
+	
+	/// Accounts for a route request received from `neighbor`, duplicates are only dropped while it is throttled
+	pub fn check_neighbor(&mut self, neighbor: ATAddress, is_new_request: bool, now: Instant) -> Throttling {
+		self.throttled.retain(|_, &mut until| now < until);
+		
+		if self.throttled.contains_key(&neighbor) {
+			return Throttling::Ongoing;
+		}
+		
+		if !is_new_request {
+			return Throttling::None;
+		}
+		
+		let limit = self.limits.per_neighbor;
+		
+		self.neighbors.retain(|_, window| {
+			window.prune(limit, now);
+			!window.requests.is_empty()
+		});
+		
+		let is_recorded = self.neighbors.entry(neighbor)
+			.or_default()
+			.try_record(limit, now);
+		
+		if is_recorded {
+			return Throttling::None;
+		}
+		
+		self.neighbors.remove(&neighbor);
+		self.throttled.insert(neighbor, now + self.limits.throttle_time);
+		Throttling::Started
+	}
+	
+	/// Accounts for a route request that is about to be forwarded, if the limits allow it
+	pub fn allow_forward(&mut self, origin: ATAddress, now: Instant) -> Result<(), RateLimited> {
+		let per_origin = self.limits.per_origin;
+		
+		self.origins.retain(|_, window| {
+			window.prune(per_origin, now);
+			!window.requests.is_empty()
+		});
+		
+		let origin_window = self.origins.entry(origin)
+			.or_default();
+		
+		if origin_window.is_full(per_origin, now) {
+			return Err(RateLimited::Origin);
+		}
+		
+		if !self.global.try_record(self.limits.global, now) {
+			return Err(RateLimited::Global);
+		}
+		
+		origin_window.requests.push_back(now);
+		Ok(())
+	}
+	
+	/// Neighbors whose route requests are currently dropped, with the time they are dropped until
+	pub fn throttled_neighbors(&self, now: Instant) -> Vec<(ATAddress, Instant)> {
+		self.throttled.iter()
+			.filter(|(_, &until)| now < until)
+			.map(|(&neighbor, &until)| (neighbor, until))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const LIMIT: RateLimit = RateLimit {
+		max_requests: 2,
+		window: Duration::from_secs(1),
+	};
+	
+	fn limiter() -> RouteRequestLimiter {
+		RouteRequestLimiter::new(RouteRequestLimits {
+			own: LIMIT,
+			per_origin: LIMIT,
+			global: RateLimit {
+				max_requests: 3,
+				..LIMIT
+			},
+			per_neighbor: LIMIT,
+			throttle_time: Duration::from_secs(10),
+		})
+	}
+	
+	fn address(address: &[u8; 4]) -> ATAddress {
+		ATAddress::new(*address).unwrap()
+	}
+	
+	#[test]
+	fn limits_own_requests() {
+		let mut limiter = limiter();
+		let now = Instant::now();
+		
+		assert!(limiter.allow_own(now));
+		assert!(limiter.allow_own(now));
+		assert!(!limiter.allow_own(now));
+		assert!(limiter.allow_own(now + LIMIT.window));
+	}
+	
+	#[test]
+	fn limits_per_origin_and_globally() {
+		let mut limiter = limiter();
+		let now = Instant::now();
+		
+		assert_eq!(limiter.allow_forward(address(b"0001"), now), Ok(()));
+		assert_eq!(limiter.allow_forward(address(b"0001"), now), Ok(()));
+		assert_eq!(limiter.allow_forward(address(b"0001"), now), Err(RateLimited::Origin));
+		
+		assert_eq!(limiter.allow_forward(address(b"0002"), now), Ok(()));
+		assert_eq!(limiter.allow_forward(address(b"0003"), now), Err(RateLimited::Global));
+		
+		assert_eq!(limiter.allow_forward(address(b"0001"), now + LIMIT.window), Ok(()));
+	}
+	
+	#[test]
+	fn throttles_flooding_neighbor() {
+		let mut limiter = limiter();
+		let neighbor = address(b"0001");
+		let now = Instant::now();
+		
+		assert_eq!(limiter.check_neighbor(neighbor, true, now), Throttling::None);
+		assert_eq!(limiter.check_neighbor(neighbor, true, now), Throttling::None);
+		// duplicates don't count
+		assert_eq!(limiter.check_neighbor(neighbor, false, now), Throttling::None);
+		assert_eq!(limiter.check_neighbor(neighbor, true, now), Throttling::Started);
+		
+		assert_eq!(limiter.check_neighbor(neighbor, false, now + LIMIT.window), Throttling::Ongoing);
+		assert_eq!(limiter.throttled_neighbors(now), vec![(neighbor, now + Duration::from_secs(10))]);
+		
+		assert_eq!(limiter.check_neighbor(neighbor, true, now + Duration::from_secs(10)), Throttling::None);
+		assert!(limiter.throttled_neighbors(now + Duration::from_secs(10)).is_empty());
+	}
+}
\ No newline at end of file
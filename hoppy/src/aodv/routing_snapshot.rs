@@ -0,0 +1,205 @@
+use std::{collections::BTreeSet, fmt::Write as _, time::Duration};
+
+use crate::at_module::at_address::ATAddress;
+
+/// Read-only copy of a node's routing table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingSnapshot {
+	/// Address of the node the routing table belongs to
+	pub address: ATAddress,
+	/// Known destinations, ordered by address
+	pub entries: Vec<RoutingEntry>,
+}
+
+/// Routes to one destination in a `RoutingSnapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingEntry {
+	pub destination: ATAddress,
+	/// Newest sequence number known for the destination
+	pub destination_sequence: u16,
+	/// Usable routes, the preferred one first, empty if the destination became unreachable
+	pub routes: Vec<RouteInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteInfo {
+	pub next_hop: ATAddress,
+	/// Neighbor of the destination on this route, if known
+	pub last_hop: Option<ATAddress>,
+	pub hop_count: u8,
+	/// Accumulated link costs according to the route metric
+	pub metric: u16,
+	/// Time since the route was last confirmed when the snapshot was taken
+	pub age: Duration,
+	/// Configured by hand instead of discovered, see `StaticRoute`
+	pub pinned: bool,
+}
+
+impl RoutingSnapshot {
+	/// Destinations that can currently be reached
+	pub fn reachable(&self) -> impl Iterator<Item = &RoutingEntry> {
+		self.entries.iter()
+			.filter(|entry| !entry.routes.is_empty())
+	}
+	
+	pub fn to_json(&self) -> String {
+		let mut json = String::new();
+		
+		write!(json, "{{\"address\":\"{}\",\"entries\":[", self.address)
+			.expect("writing to a String does not fail");
+		
+		for (index, entry) in self.entries.iter().enumerate() {
+			if index > 0 {
+				json.push(',');
+			}
+			
+			write!(json, "{{\"destination\":\"{}\",\"destination_sequence\":{},\"routes\":[", entry.destination, entry.destination_sequence)
+				.expect("writing to a String does not fail");
+			
+			for (index, route) in entry.routes.iter().enumerate() {
+				if index > 0 {
+					json.push(',');
+				}
+				
+				let last_hop = match route.last_hop {
+					Some(last_hop) => format!("\"{last_hop}\""),
+					None => "null".to_owned(),
+				};
+				
+				write!(
+					json,
+					"{{\"next_hop\":\"{}\",\"last_hop\":{last_hop},\"hop_count\":{},\"metric\":{},\"age_secs\":{},\"pinned\":{}}}",
+					route.next_hop,
+					route.hop_count,
+					route.metric,
+					route.age.as_secs_f64(),
+					route.pinned,
+				).expect("writing to a String does not fail");
+			}
+			
+			json.push_str("]}");
+		}
+		
+		json.push_str("]}");
+		json
+	}
+	
+	/// Renders the node's view of the mesh for Graphviz
+	///
+	/// Solid edges are links from this node to next hops, dashed edges lead from a next hop to the destination it is used for.
+	/// Alternative routes are drawn in gray and unreachable destinations with a dashed outline.
+	pub fn to_dot(&self) -> String {
+		let mut dot = String::new();
+		let mut next_hops = BTreeSet::new();
+		
+		writeln!(dot, "digraph routing_table {{\n\t\"{}\" [shape=doublecircle];", self.address)
+			.expect("writing to a String does not fail");
+		
+		for entry in &self.entries {
+			let destination = entry.destination;
+			
+			if entry.routes.is_empty() {
+				writeln!(dot, "\t\"{destination}\" [style=dashed];")
+			} else {
+				writeln!(dot, "\t\"{destination}\";")
+			}.expect("writing to a String does not fail");
+			
+			for (index, route) in entry.routes.iter().enumerate() {
+				next_hops.insert(route.next_hop);
+				
+				if route.next_hop == destination {
+					continue;
+				}
+				
+				let color = if index == 0 { "black" } else { "gray" };
+				
+				writeln!(
+					dot,
+					"\t\"{}\" -> \"{destination}\" [style=dashed, color={color}, label=\"{} hops, metric {}\"];",
+					route.next_hop,
+					route.hop_count,
+					route.metric,
+				).expect("writing to a String does not fail");
+			}
+		}
+		
+		for next_hop in next_hops {
+			writeln!(dot, "\t\"{}\" -> \"{next_hop}\";", self.address)
+				.expect("writing to a String does not fail");
+		}
+		
+		dot.push('}');
+		dot
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn address(address: &[u8; 4]) -> ATAddress {
+		ATAddress::new(*address).unwrap()
+	}
+	
+	fn snapshot() -> RoutingSnapshot {
+		RoutingSnapshot {
+			address: address(b"0000"),
+			entries: vec![
+				RoutingEntry {
+					destination: address(b"0001"),
+					destination_sequence: 4,
+					routes: vec![RouteInfo {
+						next_hop: address(b"0001"),
+						last_hop: Some(address(b"0000")),
+						hop_count: 1,
+						metric: 10,
+						age: Duration::from_millis(1500),
+						pinned: false,
+					}],
+				},
+				RoutingEntry {
+					destination: address(b"0002"),
+					destination_sequence: 7,
+					routes: vec![RouteInfo {
+						next_hop: address(b"0001"),
+						last_hop: None,
+						hop_count: 2,
+						metric: 20,
+						age: Duration::from_secs(3),
+						pinned: true,
+					}],
+				},
+				RoutingEntry {
+					destination: address(b"0003"),
+					destination_sequence: 1,
+					routes: Vec::new(),
+				},
+			],
+		}
+	}
+	
+	#[test]
+	fn exports_json() {
+		assert_eq!(snapshot().to_json(), concat!(
+			r#"{"address":"0000","entries":["#,
+			r#"{"destination":"0001","destination_sequence":4,"routes":[{"next_hop":"0001","last_hop":"0000","hop_count":1,"metric":10,"age_secs":1.5,"pinned":false}]},"#,
+			r#"{"destination":"0002","destination_sequence":7,"routes":[{"next_hop":"0001","last_hop":null,"hop_count":2,"metric":20,"age_secs":3,"pinned":true}]},"#,
+			r#"{"destination":"0003","destination_sequence":1,"routes":[]}"#,
+			"]}",
+		));
+	}
+	
+	#[test]
+	fn exports_dot() {
+		let dot = snapshot().to_dot();
+		
+		assert!(dot.starts_with("digraph routing_table {\n\t\"0000\" [shape=doublecircle];\n"));
+		assert!(dot.contains("\t\"0001\" -> \"0002\" [style=dashed, color=black, label=\"2 hops, metric 20\"];\n"));
+		assert!(dot.contains("\t\"0003\" [style=dashed];\n"));
+		// only one edge to the next hop, even though two routes use it
+		assert_eq!(dot.matches("\"0000\" -> \"0001\"").count(), 1);
+		assert!(dot.ends_with('}'));
+		
+		assert_eq!(snapshot().reachable().count(), 2);
+	}
+}
\ No newline at end of file
@@ -1,10 +1,22 @@
-use std::{collections::BTreeMap, fmt::Display, time::Instant};
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display, time::{Duration, Instant}};
+
+use tracing::debug;
 
 use crate::{at_module::at_address::ATAddress, aodv::sequence_number_newer};
 
-#[derive(Debug, Clone, Copy)]
+use super::{neighbor_table::NeighborTable, routing_snapshot::{RouteInfo, RoutingEntry, RoutingSnapshot}};
+
+const MAX_ROUTES_PER_DESTINATION: usize = 3;
+
+#[derive(Debug, Clone)]
 enum Entry {
-	Route(Route),
+	Routes {
+		destination_sequence: u16,
+		/// Hop count of the first route for this sequence number, alternative routes can't be longer without risking loops
+		advertised_hop_count: u8,
+		/// Link-disjoint routes, cheapest first
+		routes: Vec<Route>,
+	},
 	UnreachableDestination {
 		destination_sequence: u16,
 	}
@@ -14,158 +26,622 @@ enum Entry {
 pub struct Route {
 	pub destination_sequence: u16,
 	pub next_hop: ATAddress,
+	/// Neighbor of the destination on this route, if known
+	pub last_hop: Option<ATAddress>,
 	pub hop_count: u8,
+	/// Accumulated link costs according to the route metric
+	pub metric: u16,
 	pub last_seen: Instant,
 }
 
+impl Route {
+	fn is_disjoint(&self, other: &Route) -> bool {
+		if self.next_hop == other.next_hop {
+			return false;
+		}
+		
+		match (self.last_hop, other.last_hop) {
+			(Some(last_hop), Some(other_last_hop)) => last_hop != other_last_hop,
+			_ => true,
+		}
+	}
+}
+
+/// A route configured by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticRoute {
+	pub next_hop: ATAddress,
+	/// Advertised in route replies for the destination
+	pub hop_count: u8,
+	/// Advertised in route replies for the destination, see `LINK_COST_UNIT`
+	pub metric: u16,
+}
+
 pub struct RoutingTable {
 	entries: BTreeMap<ATAddress, Entry>,
+	/// Pinned routes with the time they were pinned, used instead of discovered routes and never removed by link failures
+	static_routes: BTreeMap<ATAddress, (StaticRoute, Instant)>,
+	/// Direct links, consulted for routes to neighbors instead of storing them as routes
+	neighbors: NeighborTable,
 	own_address: ATAddress,
 }
 
 impl RoutingTable {
-	pub fn new(own_address: ATAddress) -> Self {
+	pub fn new(own_address: ATAddress, neighbors: NeighborTable, now: Instant) -> Self {
 		let mut entries = BTreeMap::new();
-		entries.insert(own_address, Entry::Route(Route {
+		entries.insert(own_address, Entry::Routes {
 			destination_sequence: 0,
-			next_hop: own_address,
-			hop_count: 0,
-			last_seen: Instant::now(),
-		}));
+			advertised_hop_count: 0,
+			routes: vec![Route {
+				destination_sequence: 0,
+				next_hop: own_address,
+				last_hop: None,
+				hop_count: 0,
+				metric: 0,
+				last_seen: now,
+			}],
+		});
 		
 		let routing_table = Self {
 			entries,
+			static_routes: BTreeMap::new(),
+			neighbors,
 			own_address,
 		};
 		
-		println!("[INFO] Routing table updated:\n{routing_table}");
+		debug!("Routing table updated:\n{routing_table}");
 		
 		routing_table
 	}
 	
+	pub fn neighbors(&self) -> &NeighborTable {
+		&self.neighbors
+	}
+	
+	pub fn neighbors_mut(&mut self) -> &mut NeighborTable {
+		&mut self.neighbors
+	}
+	
+	/// The route over the direct link if `destination` is a neighbor that is up
+	fn direct_route(&self, destination: ATAddress) -> Option<Route> {
+		let link = self.neighbors.link(destination)?;
+		
+		Some(Route {
+			destination_sequence: link.sequence,
+			next_hop: destination,
+			last_hop: Some(self.own_address),
+			hop_count: 1,
+			metric: link.link_cost,
+			last_seen: link.last_heard,
+		})
+	}
+	
+	/// The pinned route to `destination`, fresh enough for any requested sequence number
+	fn static_route(&self, destination: ATAddress, destination_sequence: Option<u16>) -> Option<Route> {
+		let &(StaticRoute { next_hop, hop_count, metric }, pinned) = self.static_routes.get(&destination)?;
+		
+		let destination_sequence = match (self.get_last_known_sequence(destination), destination_sequence) {
+			(Some(known_sequence), Some(requested_sequence)) if sequence_number_newer(known_sequence, requested_sequence) => known_sequence,
+			(_, Some(requested_sequence)) => requested_sequence,
+			(known_sequence, None) => known_sequence.unwrap_or_default(),
+		};
+		
+		Some(Route {
+			destination_sequence,
+			next_hop,
+			last_hop: None,
+			hop_count,
+			metric,
+			last_seen: pinned,
+		})
+	}
+	
+	/// Returns the preferred route to `destination`
 	pub fn get_route(&self, destination: ATAddress, destination_sequence: Option<u16>) -> Option<Route> {
-		self.entries.get(&destination)
-			.map(|entry| match (entry, destination_sequence) {
-				(Entry::Route(route), Some(destination_sequence)) => {
-					if destination == self.own_address {
-						Some(route)
-					} else if sequence_number_newer(route.destination_sequence, destination_sequence) {
-						Some(route)
-					} else {
-						None
-					}
-				},
-				(Entry::Route(route), None) => Some(route),
-				_ => None,
+		if let Some(static_route) = self.static_route(destination, destination_sequence) {
+			return Some(static_route);
+		}
+		
+		let stored_route = self.entries.get(&destination)
+			.and_then(|entry| match entry {
+				Entry::Routes { routes, .. } => routes.first(),
+				Entry::UnreachableDestination { .. } => None,
 			})
-			.flatten()
-			.copied()
+			.copied();
+		
+		if destination == self.own_address {
+			return stored_route;
+		}
+		
+		let route = match (stored_route, self.direct_route(destination)) {
+			(Some(stored_route), Some(direct_route)) if is_direct_preferred(&direct_route, &stored_route) => direct_route,
+			(Some(stored_route), Some(_)) => stored_route,
+			(route, None) | (None, route) => route?,
+		};
+		
+		match destination_sequence {
+			Some(destination_sequence) if !sequence_number_newer(route.destination_sequence, destination_sequence) => None,
+			_ => Some(route),
+		}
 	}
 	
 	pub fn get_last_known_sequence(&self, destination: ATAddress) -> Option<u16> {
-		self.entries.get(&destination)
+		let stored_sequence = self.entries.get(&destination)
 			.map(|entry| match entry {
-				Entry::Route(Route { destination_sequence, .. }) => destination_sequence,
+				Entry::Routes { destination_sequence, .. } => destination_sequence,
 				Entry::UnreachableDestination { destination_sequence } => destination_sequence,
 			})
-			.copied()
+			.copied();
+		
+		let neighbor_sequence = self.neighbors.link(destination)
+			.map(|link| link.sequence);
+		
+		match (stored_sequence, neighbor_sequence) {
+			(Some(stored_sequence), Some(neighbor_sequence)) if sequence_number_newer(neighbor_sequence, stored_sequence) => Some(neighbor_sequence),
+			(Some(stored_sequence), _) => Some(stored_sequence),
+			(None, neighbor_sequence) => neighbor_sequence,
+		}
 	}
 	
-	pub fn add_route(&mut self, destination: ATAddress, destination_sequence: u16, next_hop: ATAddress, hop_count: u8) -> Option<Route> {
-		if destination == self.own_address {
-			return None;
+	/// Whether a route to `destination` over `next_hop` is stored
+	pub fn has_route(&self, destination: ATAddress, next_hop: ATAddress) -> bool {
+		match self.entries.get(&destination) {
+			Some(Entry::Routes { routes, .. }) => routes.iter().any(|route| route.next_hop == next_hop),
+			_ => false,
 		}
-		
-		if let Some(Entry::Route(route)) = self.entries.get(&destination) {
-			if !sequence_number_newer(destination_sequence, route.destination_sequence) {
-				return None;
-			}
+	}
+	
+	/// Returns the route if it was added, either replacing outdated routes or as an alternative
+	#[allow(clippy::too_many_arguments)]
+	pub fn add_route(
+		&mut self,
+		destination: ATAddress,
+		destination_sequence: u16,
+		next_hop: ATAddress,
+		hop_count: u8,
+		metric: u16,
+		last_hop: Option<ATAddress>,
+		now: Instant,
+	) -> Option<Route> {
+		if destination == self.own_address || self.static_routes.contains_key(&destination) {
+			return None;
 		}
 		
 		let new_route = Route {
 			destination_sequence,
 			next_hop,
+			last_hop,
 			hop_count,
-			last_seen: Instant::now(),
+			metric,
+			last_seen: now,
 		};
 		
-		let old_route = self.entries.insert(destination, Entry::Route(new_route));
-		
-		if let Some(Entry::Route(old_route)) = old_route {
-			if old_route.destination_sequence == destination_sequence &&
-				old_route.next_hop == next_hop &&
-				old_route.hop_count == hop_count
-			{
-				// route was not updated, only last_seen
+		match self.entries.get_mut(&destination) {
+			Some(Entry::Routes { destination_sequence: route_sequence, advertised_hop_count, routes }) if *route_sequence == destination_sequence => {
+				if let Some(route) = routes.iter_mut().find(|route| route.next_hop == next_hop) {
+					route.last_seen = new_route.last_seen;
+					
+					if route.metric == metric {
+						// route was not updated, only last_seen
+						return None;
+					}
+					
+					// link quality changed since the route was learned
+					route.metric = metric;
+				} else {
+					let is_acceptable = hop_count <= *advertised_hop_count &&
+						routes.iter().all(|route| route.is_disjoint(&new_route));
+					
+					if !is_acceptable {
+						return None;
+					}
+					
+					if routes.len() >= MAX_ROUTES_PER_DESTINATION {
+						let worst_route = routes.last()
+							.expect("MAX_ROUTES_PER_DESTINATION is not 0");
+						
+						if worst_route.metric <= metric {
+							return None;
+						}
+						
+						routes.pop();
+					}
+					
+					routes.push(new_route);
+				}
+				
+				routes.sort_by_key(|route| (route.metric, route.hop_count));
+			},
+			Some(Entry::Routes { destination_sequence: route_sequence, .. }) if !sequence_number_newer(destination_sequence, *route_sequence) => {
 				return None;
-			}
+			},
+			_ => {
+				self.entries.insert(destination, Entry::Routes {
+					destination_sequence,
+					advertised_hop_count: hop_count,
+					routes: vec![new_route],
+				});
+			},
 		}
 		
-		println!("[INFO] Routing table updated:\n{self}");
+		debug!(%destination, %next_hop, hop_count, metric, "Routing table updated:\n{self}");
 		
 		Some(new_route)
 	}
 	
+	/// Removes the route to `destination` over `next_hop`, alternative routes remain usable
 	pub fn remove_route(&mut self, destination: ATAddress, next_hop: ATAddress) -> bool {
 		let Some(entry) = self.entries.get_mut(&destination) else {
 			return false;
 		};
 		
-		let Entry::Route(route) = entry else {
+		let Entry::Routes { destination_sequence, routes, .. } = entry else {
 			return false;
 		};
 		
-		if route.next_hop != next_hop {
+		let route_count = routes.len();
+		routes.retain(|route| route.next_hop != next_hop);
+		
+		if routes.len() == route_count {
 			return false;
 		}
 		
-		let unreachable_destination = Entry::UnreachableDestination {
-			destination_sequence: route.destination_sequence,
-		};
+		if routes.is_empty() {
+			*entry = Entry::UnreachableDestination {
+				destination_sequence: *destination_sequence,
+			};
+		}
 		
-		*entry = unreachable_destination;
+		debug!(%destination, %next_hop, "Routing table updated:\n{self}");
+		true
+	}
+	
+	/// Marks the route to `destination` over `next_hop` as active, if it is stored
+	pub fn refresh_route(&mut self, destination: ATAddress, next_hop: ATAddress, now: Instant) {
+		if let Some(Entry::Routes { routes, .. }) = self.entries.get_mut(&destination) {
+			if let Some(route) = routes.iter_mut().find(|route| route.next_hop == next_hop) {
+				route.last_seen = route.last_seen.max(now);
+			}
+		}
+	}
+	
+	/// Routes that weren't confirmed or refreshed for longer than `timeout`, as destination and next hop
+	pub fn stale_routes(&self, now: Instant, timeout: Duration) -> Vec<(ATAddress, ATAddress)> {
+		self.routes()
+			.filter(|&(destination, route)| destination != self.own_address && now.saturating_duration_since(route.last_seen) > timeout)
+			.map(|(destination, route)| (destination, route.next_hop))
+			.collect()
+	}
+	
+	/// Uses `route` for `destination` until it is unpinned, returning the route pinned before
+	pub fn pin_route(&mut self, destination: ATAddress, route: StaticRoute, now: Instant) -> Option<StaticRoute> {
+		let previous_route = self.static_routes.insert(destination, (route, now))
+			.map(|(route, _)| route);
+		
+		debug!(%destination, next_hop = %route.next_hop, "Routing table updated:\n{self}");
 		
-		println!("[INFO] Routing table updated:\n{self}");
-		return true;
+		previous_route
+	}
+	
+	/// Goes back to discovered routes for `destination`, returning the route pinned before
+	pub fn unpin_route(&mut self, destination: ATAddress) -> Option<StaticRoute> {
+		let (route, _) = self.static_routes.remove(&destination)?;
+		
+		debug!(%destination, next_hop = %route.next_hop, "Routing table updated:\n{self}");
+		
+		Some(route)
+	}
+	
+	pub fn static_routes(&self) -> impl Iterator<Item = (ATAddress, StaticRoute)> + '_ {
+		self.static_routes.iter()
+			.map(|(&destination, &(route, _))| (destination, route))
 	}
 	
-	pub fn neighbors(&self) -> impl Iterator<Item = Route> + '_ {
+	fn routes(&self) -> impl Iterator<Item = (ATAddress, Route)> + '_ {
 		self.entries.iter()
-			.filter_map(|(&destination, &entry)| match entry {
-				Entry::Route(route) => Some((destination, route)),
-				_ => None,
+			.flat_map(|(&destination, entry)| {
+				let routes = match entry {
+					Entry::Routes { routes, .. } => routes.as_slice(),
+					Entry::UnreachableDestination { .. } => &[],
+				};
+				
+				routes.iter()
+					.map(move |&route| (destination, route))
 			})
-			.filter(|(destination, route)| route.next_hop == *destination && *destination != self.own_address)
-			.map(|(_, route)| route)
 	}
 	
-	pub fn routes_with_next_hop(&self, next_hop: ATAddress) -> impl Iterator<Item = (ATAddress, Route)> + '_ {
-		self.entries.iter()
-			.filter_map(|(&destination, &entry)| match entry {
-				Entry::Route(route) => Some((destination, route)),
-				_ => None,
+	/// Copies the routes to all other nodes, including direct links to neighbors that are up
+	pub fn snapshot(&self, now: Instant) -> RoutingSnapshot {
+		let destinations: BTreeSet<ATAddress> = self.entries.keys()
+			.copied()
+			.chain(self.static_routes.keys().copied())
+			.chain(self.neighbors.up_neighbors())
+			.filter(|&destination| destination != self.own_address)
+			.collect();
+		
+		let entries = destinations.into_iter()
+			.map(|destination| {
+				let stored_routes = match self.entries.get(&destination) {
+					Some(Entry::Routes { routes, .. }) => routes.as_slice(),
+					_ => &[],
+				};
+				
+				let mut routes: Vec<Route> = stored_routes.to_vec();
+				
+				if let Some(direct_route) = self.direct_route(destination) {
+					match routes.first() {
+						Some(stored_route) if !is_direct_preferred(&direct_route, stored_route) => routes.push(direct_route),
+						_ => routes.insert(0, direct_route),
+					}
+				}
+				
+				// a pinned route is used before all others
+				let static_route = self.static_route(destination, None)
+					.map(|route| (route, true));
+				
+				RoutingEntry {
+					destination,
+					destination_sequence: self.get_last_known_sequence(destination)
+						.unwrap_or_default(),
+					routes: static_route.into_iter()
+						.chain(routes.into_iter().map(|route| (route, false)))
+						.map(|(route, pinned)| RouteInfo {
+							next_hop: route.next_hop,
+							last_hop: route.last_hop,
+							hop_count: route.hop_count,
+							metric: route.metric,
+							age: now.saturating_duration_since(route.last_seen),
+							pinned,
+						})
+						.collect(),
+				}
 			})
+			.collect();
+		
+		RoutingSnapshot {
+			address: self.own_address,
+			entries,
+		}
+	}
+	
+	/// Number of stored routes, alternatives included and neighbors excluded
+	pub fn route_count(&self) -> usize {
+		self.routes().count()
+	}
+	
+	pub fn routes_with_next_hop(&self, next_hop: ATAddress) -> impl Iterator<Item = (ATAddress, Route)> + '_ {
+		self.routes()
 			.filter(move |(_, route)| route.next_hop == next_hop)
 	}
 }
 
+/// Whether the direct link to a neighbor should be used instead of the stored route to it
+fn is_direct_preferred(direct_route: &Route, stored_route: &Route) -> bool {
+	sequence_number_newer(direct_route.destination_sequence, stored_route.destination_sequence) ||
+		(direct_route.destination_sequence == stored_route.destination_sequence &&
+		(direct_route.metric, direct_route.hop_count) <= (stored_route.metric, stored_route.hop_count))
+}
+
 impl Display for RoutingTable {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		writeln!(f, "+----+----+----+----+")?;
-		writeln!(f, "|DEST|DSEQ|NHOP|HCNT|")?;
-		writeln!(f, "+----+----+----+----+")?;
+		writeln!(f, "+----+----+----+----+----+")?;
+		writeln!(f, "|DEST|DSEQ|NHOP|HCNT|METR|")?;
+		writeln!(f, "+----+----+----+----+----+")?;
 		
 		for (destination, entry) in &self.entries {
 			match entry {
-				Entry::Route(Route { destination_sequence, next_hop, hop_count, .. }) => {
-					writeln!(f, "|{destination}|{destination_sequence:04X}|{next_hop}|  {hop_count:02X}|")?;
+				Entry::Routes { routes, .. } => {
+					for Route { destination_sequence, next_hop, hop_count, metric, .. } in routes {
+						writeln!(f, "|{destination}|{destination_sequence:04X}|{next_hop}|  {hop_count:02X}|{metric:04X}|")?;
+					}
 				},
 				Entry::UnreachableDestination { destination_sequence } => {
-					writeln!(f, "|{destination}|{destination_sequence:04X}|None|None|")?;
+					writeln!(f, "|{destination}|{destination_sequence:04X}|None|None|None|")?;
 				},
 			}
 		}
 		
-		write!(f, "+----+----+----+----+")
+		for (destination, (StaticRoute { next_hop, hop_count, metric }, _)) in &self.static_routes {
+			writeln!(f, "|{destination}| PIN|{next_hop}|  {hop_count:02X}|{metric:04X}|")?;
+		}
+		
+		write!(f, "+----+----+----+----+----+")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::aodv::{node_info::NodeInfo, packets::HelloPacket};
+	
+	use super::*;
+	
+	const HELLO_INTERVAL: Duration = Duration::from_secs(10);
+	
+	fn address(address: &[u8; 4]) -> ATAddress {
+		ATAddress::new(*address).unwrap()
+	}
+	
+	fn hello(sequence: u16) -> HelloPacket {
+		HelloPacket {
+			sequence,
+			interval: HELLO_INTERVAL,
+			info: NodeInfo::default(),
+			neighbors: Vec::new(),
+		}
+	}
+	
+	fn routing_table() -> RoutingTable {
+		RoutingTable::new(address(b"0000"), NeighborTable::new(HELLO_INTERVAL, HELLO_INTERVAL * 3), Instant::now())
+	}
+	
+	#[test]
+	fn keeps_disjoint_alternatives() {
+		let mut routing_table = routing_table();
+		let destination = address(b"0009");
+		
+		assert!(routing_table.add_route(destination, 1, address(b"0001"), 3, 30, Some(address(b"0005")), Instant::now()).is_some());
+		assert!(routing_table.add_route(destination, 1, address(b"0002"), 2, 20, Some(address(b"0006")), Instant::now()).is_some());
+		
+		let route = routing_table.get_route(destination, None).unwrap();
+		assert_eq!(route.next_hop, address(b"0002"));
+		
+		// shares the last hop with an existing route
+		assert!(routing_table.add_route(destination, 1, address(b"0003"), 2, 20, Some(address(b"0005")), Instant::now()).is_none());
+		// longer than the advertised hop count
+		assert!(routing_table.add_route(destination, 1, address(b"0004"), 4, 40, None, Instant::now()).is_none());
+		
+		assert_eq!(routing_table.routes_with_next_hop(address(b"0001")).count(), 1);
+	}
+	
+	#[test]
+	fn newer_sequence_replaces_alternatives() {
+		let mut routing_table = routing_table();
+		let destination = address(b"0009");
+		
+		routing_table.add_route(destination, 1, address(b"0001"), 2, 20, None, Instant::now());
+		routing_table.add_route(destination, 1, address(b"0002"), 2, 20, None, Instant::now());
+		routing_table.add_route(destination, 2, address(b"0003"), 3, 30, None, Instant::now());
+		
+		assert_eq!(routing_table.routes().filter(|(dest, _)| *dest == destination).count(), 1);
+		assert_eq!(routing_table.get_route(destination, None).unwrap().next_hop, address(b"0003"));
+	}
+	
+	#[test]
+	fn fails_over_to_alternative() {
+		let mut routing_table = routing_table();
+		let destination = address(b"0009");
+		
+		routing_table.add_route(destination, 1, address(b"0001"), 2, 20, None, Instant::now());
+		routing_table.add_route(destination, 1, address(b"0002"), 2, 20, None, Instant::now());
+		
+		assert!(routing_table.remove_route(destination, address(b"0001")));
+		assert_eq!(routing_table.get_route(destination, None).unwrap().next_hop, address(b"0002"));
+		
+		assert!(routing_table.remove_route(destination, address(b"0002")));
+		assert!(routing_table.get_route(destination, None).is_none());
+		assert_eq!(routing_table.get_last_known_sequence(destination), Some(1));
+	}
+	
+	#[test]
+	fn prefers_lower_metric_over_hop_count() {
+		let mut routing_table = routing_table();
+		let destination = address(b"0009");
+		
+		// a direct but lossy link
+		routing_table.add_route(destination, 1, address(b"0009"), 2, 90, None, Instant::now());
+		routing_table.add_route(destination, 1, address(b"0001"), 2, 20, None, Instant::now());
+		
+		assert_eq!(routing_table.get_route(destination, None).unwrap().next_hop, address(b"0001"));
+		
+		// link quality of the direct link improved
+		assert!(routing_table.add_route(destination, 1, address(b"0009"), 2, 10, None, Instant::now()).is_some());
+		assert_eq!(routing_table.get_route(destination, None).unwrap().next_hop, address(b"0009"));
+	}
+	
+	#[test]
+	fn replaces_worst_alternative() {
+		let mut routing_table = routing_table();
+		let destination = address(b"0009");
+		
+		for (next_hop, metric) in [(b"0001", 20), (b"0002", 30), (b"0003", 40)] {
+			routing_table.add_route(destination, 1, address(next_hop), 2, metric, None, Instant::now());
+		}
+		
+		assert!(routing_table.add_route(destination, 1, address(b"0004"), 2, 50, None, Instant::now()).is_none());
+		assert!(routing_table.add_route(destination, 1, address(b"0004"), 2, 25, None, Instant::now()).is_some());
+		assert_eq!(routing_table.routes_with_next_hop(address(b"0003")).count(), 0);
+	}
+	
+	#[test]
+	fn consults_neighbor_table() {
+		let mut routing_table = routing_table();
+		let neighbor = address(b"0001");
+		let now = Instant::now();
+		
+		assert!(routing_table.get_route(neighbor, None).is_none());
+		
+		routing_table.neighbors_mut().record_hello(neighbor, &hello(5), 10, now);
+		
+		let route = routing_table.get_route(neighbor, None).unwrap();
+		assert_eq!((route.next_hop, route.hop_count, route.metric), (neighbor, 1, 10));
+		assert_eq!(routing_table.get_last_known_sequence(neighbor), Some(5));
+		// neighbors are not stored as routes
+		assert_eq!(routing_table.routes_with_next_hop(neighbor).count(), 0);
+		
+		// a cheaper route over another node with the same sequence number is preferred
+		routing_table.add_route(neighbor, 5, address(b"0002"), 2, 8, None, now);
+		assert_eq!(routing_table.get_route(neighbor, None).unwrap().next_hop, address(b"0002"));
+		
+		routing_table.neighbors_mut().record_hello(neighbor, &hello(6), 10, now);
+		assert_eq!(routing_table.get_route(neighbor, None).unwrap().next_hop, neighbor);
+		
+		routing_table.neighbors_mut().mark_down(neighbor);
+		assert_eq!(routing_table.get_route(neighbor, None).unwrap().next_hop, address(b"0002"));
+		assert_eq!(routing_table.get_last_known_sequence(neighbor), Some(5));
+	}
+	
+	#[test]
+	fn pinned_route_takes_precedence() {
+		let mut routing_table = routing_table();
+		let destination = address(b"0009");
+		let static_route = StaticRoute {
+			next_hop: address(b"0003"),
+			hop_count: 3,
+			metric: 30,
+		};
+		
+		routing_table.add_route(destination, 1, address(b"0001"), 2, 20, None, Instant::now());
+		routing_table.pin_route(destination, static_route, Instant::now());
+		
+		// answers requests for any sequence number
+		let route = routing_table.get_route(destination, Some(4)).unwrap();
+		assert_eq!((route.next_hop, route.destination_sequence), (address(b"0003"), 4));
+		assert_eq!(routing_table.get_route(destination, None).unwrap().destination_sequence, 1);
+		
+		// not replaced by discovered routes, nor removed with the next hop's routes
+		assert!(routing_table.add_route(destination, 2, address(b"0002"), 1, 10, None, Instant::now()).is_none());
+		assert!(!routing_table.remove_route(destination, address(b"0003")));
+		assert_eq!(routing_table.get_route(destination, None).unwrap().next_hop, address(b"0003"));
+		
+		let snapshot = routing_table.snapshot(Instant::now());
+		let pinned: Vec<bool> = snapshot.entries[0].routes.iter()
+			.map(|route| route.pinned)
+			.collect();
+		assert_eq!(pinned, [true, false]);
+		
+		assert_eq!(routing_table.unpin_route(destination), Some(static_route));
+		assert_eq!(routing_table.get_route(destination, None).unwrap().next_hop, address(b"0001"));
+	}
+	
+	#[test]
+	fn snapshot_orders_routes_by_preference() {
+		let mut routing_table = routing_table();
+		let neighbor = address(b"0001");
+		let destination = address(b"0009");
+		let now = Instant::now();
+		
+		routing_table.neighbors_mut().record_hello(neighbor, &hello(5), 10, now);
+		routing_table.add_route(neighbor, 5, address(b"0002"), 2, 8, None, now);
+		routing_table.add_route(destination, 1, address(b"0002"), 2, 20, None, now);
+		routing_table.remove_route(destination, address(b"0002"));
+		
+		let snapshot = routing_table.snapshot(now + Duration::from_secs(2));
+		let destinations: Vec<ATAddress> = snapshot.entries.iter()
+			.map(|entry| entry.destination)
+			.collect();
+		
+		// the own address is left out
+		assert_eq!(destinations, [neighbor, destination]);
+		
+		let next_hops: Vec<ATAddress> = snapshot.entries[0].routes.iter()
+			.map(|route| route.next_hop)
+			.collect();
+		
+		// the cheaper stored route is preferred over the direct link
+		assert_eq!(next_hops, [address(b"0002"), neighbor]);
+		assert_eq!(snapshot.entries[0].routes[0].age, Duration::from_secs(2));
+		
+		assert_eq!(snapshot.entries[1].destination_sequence, 1);
+		assert!(snapshot.entries[1].routes.is_empty());
 	}
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, time::{Duration, Instant}};
+
+use crate::at_module::ATStats;
+
+use super::{delivery::DeliveryFailure, events::DropReason, packets::PacketType};
+
+/// Counters of an `AODVProtocol` since it was created or the counters were last reset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AODVStats {
+	pub since: Instant,
+	/// Frames handed to the driver, including retransmissions
+	pub frames_sent: BTreeMap<PacketType, u64>,
+	/// Frames that could be parsed, including retransmissions
+	pub frames_received: BTreeMap<PacketType, u64>,
+	/// Data passed to `AODVProtocol::send`
+	pub data_originated: u64,
+	/// Data relayed towards its destination for other nodes
+	pub data_forwarded: u64,
+	/// Data addressed to this node, without duplicates
+	pub data_delivered: u64,
+	pub packets_dropped: BTreeMap<DropReason, u64>,
+	pub deliveries_failed: BTreeMap<DeliveryFailure, u64>,
+	pub discoveries_started: u64,
+	pub discoveries_succeeded: u64,
+	pub discoveries_failed: u64,
+	/// Summed up time successful discoveries took, from the first route request to the first route
+	pub discovery_latency: Duration,
+	/// Frames sent again because the next hop did not acknowledge them
+	pub link_retries: u64,
+	/// Data sent again because the destination did not acknowledge it
+	pub delivery_retries: u64,
+}
+
+impl AODVStats {
+	pub(super) fn new(since: Instant) -> Self {
+		Self {
+			since,
+			frames_sent: BTreeMap::new(),
+			frames_received: BTreeMap::new(),
+			data_originated: 0,
+			data_forwarded: 0,
+			data_delivered: 0,
+			packets_dropped: BTreeMap::new(),
+			deliveries_failed: BTreeMap::new(),
+			discoveries_started: 0,
+			discoveries_succeeded: 0,
+			discoveries_failed: 0,
+			discovery_latency: Duration::ZERO,
+			link_retries: 0,
+			delivery_retries: 0,
+		}
+	}
+	
+	/// Average time a successful route discovery took
+	pub fn average_discovery_latency(&self) -> Option<Duration> {
+		let discoveries = u32::try_from(self.discoveries_succeeded).ok()
+			.filter(|discoveries| *discoveries > 0)?;
+		
+		Some(self.discovery_latency / discoveries)
+	}
+}
+
+/// How much work an `AODVProtocol` has queued up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueDepths {
+	/// Data waiting for a route
+	pub outbound_messages: usize,
+	/// Data sent from this node that didn't succeed or fail yet
+	pub pending_deliveries: usize,
+	/// Frames waiting for a link-layer acknowledgement
+	pub pending_link_acks: usize,
+	/// Flooded packets waiting to be rebroadcast
+	pub pending_broadcasts: usize,
+}
+
+/// Counters of a controller's protocol and AT module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerStats {
+	pub protocol: AODVStats,
+	pub at_module: ATStats,
+}
+
+pub(super) fn count<K: Ord>(counters: &mut BTreeMap<K, u64>, key: K) {
+	*counters.entry(key)
+		.or_default() += 1;
+}
\ No newline at end of file
@@ -2,12 +2,24 @@ pub mod at_address;
 
 mod config;
 mod read_replies;
+mod stats;
+
+#[cfg(feature = "tokio")]
+mod async_module;
 
 pub use config::*;
-pub use read_replies::ATMessage;
+pub use read_replies::{ATMessage, ATSignal};
+pub use stats::{ATStats, ATCounters};
+
+#[cfg(feature = "tokio")]
+pub use async_module::AsyncATModule;
+
+#[cfg(all(test, feature = "tokio"))]
+pub(crate) use async_module::MockRadio;
 
-use std::{io::{self, ErrorKind}, thread, sync::mpsc::{self, Receiver}};
+use std::{io::{self, ErrorKind}, thread::{self, JoinHandle}, sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}}};
 use serialport::SerialPort;
+use tracing::{debug, info};
 use crate::no_timeout_reader::NoTimeoutReader;
 
 use self::{read_replies::ATReply, at_address::ATAddress};
@@ -19,6 +31,9 @@ pub struct ATModuleBuilder {
 	address: ATAddress,
 	reply_receiver: Receiver<ATReply>,
 	message_receiver: Receiver<ATMessage>,
+	config: ATConfig,
+	counters: ATCounters,
+	reader: ReaderThread,
 }
 
 impl ATModuleBuilder {
@@ -27,6 +42,9 @@ impl ATModuleBuilder {
 			port: self.port,
 			address: self.address,
 			reply_receiver: self.reply_receiver,
+			config: self.config,
+			counters: self.counters,
+			_reader: self.reader,
 		};
 		
 		let message_receiver = self.message_receiver;
@@ -35,29 +53,69 @@ impl ATModuleBuilder {
 	}
 }
 
+/// Thread reading from a clone of the serial port, stopped on the next read timeout of the port
+struct ReaderThread {
+	stopped: Arc<AtomicBool>,
+	handle: Option<JoinHandle<()>>,
+}
+
+impl ReaderThread {
+	fn stop(&mut self) {
+		self.stopped.store(true, Ordering::Relaxed);
+		
+		if let Some(handle) = self.handle.take() {
+			handle.join()
+				.expect("no threads should panic");
+		}
+	}
+}
+
+impl Drop for ReaderThread {
+	fn drop(&mut self) {
+		self.stop();
+	}
+}
+
+/// Closes the serial port when dropped, which waits for the reader thread to notice on the next read timeout
 pub struct ATModule {
 	port: Box<dyn SerialPort>,
 	address: ATAddress,
 	reply_receiver: Receiver<ATReply>,
+	config: ATConfig,
+	counters: ATCounters,
+	/// Stopped when the module is dropped, after the port was closed
+	_reader: ReaderThread,
 }
 
 impl ATModule {
-	pub fn open<'scope>(
-		scope: &'scope thread::Scope<'scope, '_>,
+	pub fn open(
 		mut port: Box<dyn SerialPort>,
 		address: ATAddress,
 		config: ATConfig,
 	) -> Result<ATModuleBuilder, io::Error> {
+		config.validate()?;
+		
+		let stopped = Arc::new(AtomicBool::new(false));
+		
 		let reader = port.try_clone()?;
-		let reader = NoTimeoutReader::new(reader);
+		let reader = NoTimeoutReader::new(reader, Arc::clone(&stopped));
 		
 		let (reply_sender, reply_receiver) = mpsc::channel();
 		let (message_sender, message_receiver) = mpsc::channel();
 		
-		scope.spawn(|| {
-			read_replies(reader, reply_sender, message_sender);
+		let counters = ATCounters::new();
+		let reader_counters = counters.clone();
+		
+		let handle = thread::spawn(|| {
+			read_replies(reader, reply_sender, message_sender, reader_counters);
 		});
 		
+		// stops the reader if configuring the module fails
+		let reader = ReaderThread {
+			stopped,
+			handle: Some(handle),
+		};
+		
 		let read_reply = || {
 			reply_receiver.recv()
 				.expect("mpsc sender should not disconnect")
@@ -75,11 +133,21 @@ impl ATModule {
 			return Err(ErrorKind::Other.into());
 		}
 		
+		write!(port, "AT+RSSI=1\r\n")?;
+		
+		// not every module reports signal information, received frames are parsed either way
+		if !read_reply().is_ok() {
+			info!("AT module does not report signal information");
+		}
+		
 		Ok(ATModuleBuilder {
 			port,
 			address,
 			reply_receiver,
-			message_receiver
+			message_receiver,
+			config,
+			counters,
+			reader,
 		})
 	}
 	
@@ -87,14 +155,39 @@ impl ATModule {
 		self.address
 	}
 	
+	/// Handle to the counters of this module, which stays valid after the module was moved to another thread
+	pub fn counters(&self) -> ATCounters {
+		self.counters.clone()
+	}
+	
+	pub fn stats(&self) -> ATStats {
+		self.counters.snapshot()
+	}
+	
+	/// Starts counting from zero, returning the counters up to now
+	pub fn reset_stats(&self) -> ATStats {
+		self.counters.reset()
+	}
+	
 	fn read_reply(&mut self) -> ATReply {
 		self.reply_receiver.recv()
 			.expect("mpsc sender should not disconnect")
 	}
 	
 	pub fn send(&mut self, destination: ATAddress, data: &[u8]) -> Result<(), io::Error> {
+		let result = self.send_frame(destination, data);
+		
+		match result {
+			Ok(()) => self.counters.record_sent(data.len(), self.config.time_on_air(data.len())),
+			Err(_) => self.counters.record_error(),
+		}
+		
+		result
+	}
+	
+	fn send_frame(&mut self, destination: ATAddress, data: &[u8]) -> Result<(), io::Error> {
 		let text = String::from_utf8_lossy(data);
-		println!("[INFO] Sending:\n\t<{destination}> {text}");
+		debug!(%destination, length = data.len(), "Sending:\n\t{text}");
 		
 		write!(self.port, "AT+DEST={destination}\r\n")?;
 		
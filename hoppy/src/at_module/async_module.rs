@@ -0,0 +1,130 @@
+use std::{future::Future, io::{self, ErrorKind}, sync::mpsc, thread};
+
+use tokio::sync::{mpsc::{self as async_mpsc, UnboundedReceiver}, oneshot};
+
+use super::{ATCounters, ATMessage, ATModuleBuilder, at_address::ATAddress};
+
+struct SendRequest {
+	destination: ATAddress,
+	data: Box<[u8]>,
+	result: oneshot::Sender<Result<(), io::Error>>,
+}
+
+/// Async interface to an `ATModule`, which keeps doing the blocking serial I/O on its own threads
+///
+/// Dropping the module closes the serial port in the background.
+pub struct AsyncATModule {
+	address: ATAddress,
+	counters: ATCounters,
+	requests: mpsc::Sender<SendRequest>,
+	messages: UnboundedReceiver<ATMessage>,
+}
+
+impl AsyncATModule {
+	pub fn new(at_module_builder: ATModuleBuilder) -> Self {
+		let (mut at_module, at_message_receiver) = at_module_builder.build();
+		let address = at_module.address();
+		let counters = at_module.counters();
+		
+		let (request_sender, request_receiver) = mpsc::channel::<SendRequest>();
+		let (message_sender, message_receiver) = async_mpsc::unbounded_channel();
+		
+		thread::spawn(move || {
+			for request in request_receiver {
+				// the caller might have stopped waiting
+				let _ = request.result.send(at_module.send(request.destination, &request.data));
+			}
+		});
+		
+		thread::spawn(move || {
+			for message in at_message_receiver {
+				if message_sender.send(message).is_err() {
+					break;
+				}
+			}
+		});
+		
+		Self {
+			address,
+			counters,
+			requests: request_sender,
+			messages: message_receiver,
+		}
+	}
+	
+	pub fn address(&self) -> ATAddress {
+		self.address
+	}
+	
+	pub fn counters(&self) -> ATCounters {
+		self.counters.clone()
+	}
+	
+	/// Queues the data right away, so frames are written in the order this is called, not awaited
+	pub fn send(&self, destination: ATAddress, data: Box<[u8]>) -> impl Future<Output = Result<(), io::Error>> + Send + 'static {
+		let (result_sender, result_receiver) = oneshot::channel();
+		
+		let queued = self.requests.send(SendRequest {
+			destination,
+			data,
+			result: result_sender,
+		});
+		
+		async move {
+			queued.map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?;
+			
+			result_receiver.await
+				.map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?
+		}
+	}
+	
+	pub fn broadcast(&self, data: Box<[u8]>) -> impl Future<Output = Result<(), io::Error>> + Send + 'static {
+		self.send(ATAddress::BROADCAST, data)
+	}
+	
+	/// Waits for the next received frame, returning None once the serial port was closed
+	pub async fn recv(&mut self) -> Option<ATMessage> {
+		self.messages.recv().await
+	}
+}
+
+/// Stands in for the serial port in tests, collecting the frames the module was asked to send and passing frames to it
+#[cfg(test)]
+pub(crate) struct MockRadio {
+	pub sent: UnboundedReceiver<(ATAddress, Box<[u8]>)>,
+	pub received: async_mpsc::UnboundedSender<ATMessage>,
+}
+
+#[cfg(test)]
+impl AsyncATModule {
+	/// Module whose frames go to the returned `MockRadio` instead of a serial port, every send succeeds
+	pub(crate) fn mock(address: ATAddress) -> (Self, MockRadio) {
+		let (request_sender, request_receiver) = mpsc::channel::<SendRequest>();
+		let (sent_sender, sent_receiver) = async_mpsc::unbounded_channel();
+		let (message_sender, message_receiver) = async_mpsc::unbounded_channel();
+		
+		thread::spawn(move || {
+			for request in request_receiver {
+				let _ = request.result.send(Ok(()));
+				
+				if sent_sender.send((request.destination, request.data)).is_err() {
+					break;
+				}
+			}
+		});
+		
+		let module = Self {
+			address,
+			counters: ATCounters::new(),
+			requests: request_sender,
+			messages: message_receiver,
+		};
+		
+		let radio = MockRadio {
+			sent: sent_receiver,
+			received: message_sender,
+		};
+		
+		(module, radio)
+	}
+}
\ No newline at end of file
@@ -4,7 +4,7 @@ use std::{io::{self, ErrorKind}, fmt::{Debug, Display, self}, str, error::Error}
 pub struct ATAddress ([u8; 4]);
 
 impl ATAddress {
-	pub(super) const BROADCAST: ATAddress = ATAddress(*b"FFFF");
+	pub(crate) const BROADCAST: ATAddress = ATAddress(*b"FFFF");
 	
 	pub fn new(mut data: [u8; 4]) -> Result<Self, ATAddressError> {
 		if !validate_uppercase_hex_digits(&mut data) {
@@ -1,7 +1,8 @@
-use std::fmt::Display;
+use std::{fmt::Display, io::{self, ErrorKind}, time::Duration};
 
 // not all variants used right now
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HeaderMode {
 	Explicit,
 	Implicit,
@@ -18,6 +19,7 @@ impl Display for HeaderMode {
 
 // not all variants used right now
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReceiveMode {
 	Continue,
 	Single,
@@ -32,6 +34,7 @@ impl Display for ReceiveMode {
 	}
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct ATConfig {
 	pub frequency: u32,
 	pub power: u8,
@@ -48,6 +51,68 @@ pub struct ATConfig {
 	pub preamble_length: u16,
 }
 
+impl ATConfig {
+	/// Rejects radio settings outside of what the module supports, which `time_on_air` relies on
+	pub fn validate(&self) -> Result<(), io::Error> {
+		if !(7..=12).contains(&self.spreading_factor) {
+			return Err(io::Error::new(ErrorKind::InvalidInput, "Spreading factor must be between 7 and 12"));
+		}
+		
+		if self.bandwidth > 9 {
+			return Err(io::Error::new(ErrorKind::InvalidInput, "Bandwidth must be between 0 and 9"));
+		}
+		
+		if !(1..=4).contains(&self.error_coding) {
+			return Err(io::Error::new(ErrorKind::InvalidInput, "Error coding must be between 1 and 4"));
+		}
+		
+		Ok(())
+	}
+	
+	/// Bandwidth in Hz, the codes follow the SX127x register values
+	fn bandwidth_hz(&self) -> f64 {
+		match self.bandwidth {
+			0 => 7_800.0,
+			1 => 10_400.0,
+			2 => 15_600.0,
+			3 => 20_800.0,
+			4 => 31_250.0,
+			5 => 41_700.0,
+			6 => 62_500.0,
+			7 => 125_000.0,
+			8 => 250_000.0,
+			// only 9 is left after validation
+			_ => 500_000.0,
+		}
+	}
+	
+	/// Estimated time on air of a frame carrying `payload_length` bytes, following Semtech's LoRa modem calculation
+	///
+	/// Expects a config that passed `validate`.
+	pub fn time_on_air(&self, payload_length: usize) -> Duration {
+		let spreading_factor = self.spreading_factor as f64;
+		let symbol_time = 2f64.powf(spreading_factor) / self.bandwidth_hz();
+		
+		// modules optimize for low data rates once symbols get longer than 16ms
+		let low_data_rate = symbol_time > 0.016;
+		
+		let preamble_time = (self.preamble_length as f64 + 4.25) * symbol_time;
+		
+		let crc_bits = if self.crc { 16.0 } else { 0.0 };
+		let header_bits = match self.header_mode {
+			HeaderMode::Explicit => 0.0,
+			HeaderMode::Implicit => 20.0,
+		};
+		
+		let payload_bits = 8.0 * payload_length as f64 - 4.0 * spreading_factor + 28.0 + crc_bits - header_bits;
+		let bits_per_block = 4.0 * (spreading_factor - if low_data_rate { 2.0 } else { 0.0 });
+		// error coding 1 to 4 stands for coding rates 4/5 to 4/8
+		let payload_symbols = 8.0 + ((payload_bits / bits_per_block).ceil() * (self.error_coding as f64 + 4.0)).max(0.0);
+		
+		Duration::from_secs_f64(preamble_time + payload_symbols * symbol_time)
+	}
+}
+
 fn bool_to_digit(b: bool) -> &'static str {
 	if b {
 		"1"
@@ -76,4 +141,64 @@ impl Display for ATConfig {
 			self.preamble_length
 		)
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn config() -> ATConfig {
+		ATConfig {
+			frequency: 433920000,
+			power: 5,
+			bandwidth: 7,
+			spreading_factor: 7,
+			error_coding: 1,
+			crc: true,
+			header_mode: HeaderMode::Explicit,
+			receive_mode: ReceiveMode::Continue,
+			frequency_hop: false,
+			hop_period: 0,
+			receive_timeout: 3000,
+			payload_length: 8,
+			preamble_length: 8,
+		}
+	}
+	
+	#[test]
+	fn time_on_air_matches_semtech_calculator() {
+		let time_on_air = config().time_on_air(10);
+		
+		assert!(time_on_air.abs_diff(Duration::from_micros(41_216)) < Duration::from_micros(10));
+	}
+	
+	#[test]
+	fn rejects_unsupported_radio_settings() {
+		assert!(config().validate().is_ok());
+		
+		for spreading_factor in [0, 2, 6, 13] {
+			let invalid = ATConfig {
+				spreading_factor,
+				..config()
+			};
+			
+			assert_eq!(invalid.validate().unwrap_err().kind(), ErrorKind::InvalidInput);
+		}
+		
+		let invalid = ATConfig {
+			bandwidth: 10,
+			..config()
+		};
+		
+		assert_eq!(invalid.validate().unwrap_err().kind(), ErrorKind::InvalidInput);
+		
+		for error_coding in [0, 5] {
+			let invalid = ATConfig {
+				error_coding,
+				..config()
+			};
+			
+			assert_eq!(invalid.validate().unwrap_err().kind(), ErrorKind::InvalidInput);
+		}
+	}
 }
\ No newline at end of file
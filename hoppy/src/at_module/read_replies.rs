@@ -1,10 +1,11 @@
-use std::{io::{Read, self, ErrorKind}, sync::mpsc::Sender, fmt::Display};
+use std::{io::{Read, self, ErrorKind}, sync::mpsc::Sender, fmt::Display, str};
 
 use read_buffer::DynReadBuffer;
+use tracing::error;
 
 use crate::hex::parse_ascii_hex;
 
-use super::at_address::ATAddress;
+use super::{at_address::ATAddress, stats::ATCounters};
 
 #[derive(Debug)]
 pub struct ATReply {
@@ -25,10 +26,21 @@ impl ATReply {
 	}
 }
 
+/// Signal information of a received frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ATSignal {
+	/// Received signal strength in dBm
+	pub rssi: i16,
+	/// Signal-to-noise ratio in dB
+	pub snr: i8,
+}
+
 #[derive(Debug, Clone)]
 pub struct ATMessage {
 	pub address: ATAddress,
 	pub data: Box<[u8]>,
+	/// Only present if the AT module reports signal information
+	pub signal: Option<ATSignal>,
 }
 
 impl Display for ATMessage {
@@ -36,11 +48,17 @@ impl Display for ATMessage {
 		let address = self.address;
 		let data = String::from_utf8_lossy(&self.data);
 		
-		write!(f, "<{address}> {data}")
+		write!(f, "<{address}> {data}")?;
+		
+		if let Some(ATSignal { rssi, snr }) = self.signal {
+			write!(f, " (RSSI {rssi} dBm, SNR {snr} dB)")?;
+		}
+		
+		Ok(())
 	}
 }
 
-pub fn read_replies(reader: impl Read, reply_sender: Sender<ATReply>, message_sender: Sender<ATMessage>) {
+pub fn read_replies(reader: impl Read, reply_sender: Sender<ATReply>, message_sender: Sender<ATMessage>, counters: ATCounters) {
 	let mut buffer = DynReadBuffer::new(reader);
 	
 	loop {
@@ -48,13 +66,20 @@ pub fn read_replies(reader: impl Read, reply_sender: Sender<ATReply>, message_se
 		
 		let result = match reply_type {
 			Ok(b"AT,") => read_at(&mut buffer, &reply_sender),
-			Ok(b"LR,") => read_lr(&mut buffer, &message_sender),
+			Ok(b"LR,") => read_lr(&mut buffer, &message_sender)
+				.map(|length| counters.record_received(length)),
 			Ok(data) => Err(io::Error::new(ErrorKind::InvalidData, String::from_utf8_lossy(data))),
 			Err(err) => Err(err),
 		};
 		
-		if let Err(err) = result {
-			eprintln!("Encountered an error reading from AT module: {err}")
+		match result {
+			Ok(()) => (),
+			// the AT module was closed
+			Err(err) if err.kind() == ErrorKind::ConnectionAborted => return,
+			Err(err) => {
+				error!(%err, "Encountered an error reading from AT module");
+				counters.record_error();
+			},
 		}
 	}
 }
@@ -71,12 +96,13 @@ fn read_at(buffer: &mut DynReadBuffer<impl Read>, sender: &Sender<ATReply>) -> R
 	
 	sender.send(ATReply {
 		data: command.into()
-	}).expect("mpsc receiver should not disconnect");
+	}).map_err(|_| ErrorKind::ConnectionAborted)?;
 	
 	Ok(())
 }
 
-fn read_lr(buffer: &mut DynReadBuffer<impl Read>, message_sender: &Sender<ATMessage>) -> Result<(), io::Error> {
+/// Returns the length of the received data
+fn read_lr(buffer: &mut DynReadBuffer<impl Read>, message_sender: &Sender<ATMessage>) -> Result<usize, io::Error> {
 	let header = buffer.read_bytes(8)?;
 	
 	if header[4] != b',' || header[7] != b',' {
@@ -89,19 +115,95 @@ fn read_lr(buffer: &mut DynReadBuffer<impl Read>, message_sender: &Sender<ATMess
 	let length = &header[5..=6];
 	let length: u8 = parse_ascii_hex(length)?;
 	
-	let data = buffer.read_bytes(length as usize + 2)?;
+	let data = buffer.read_bytes(length as usize + 1)?;
 	
-	if data[data.len() - 2..] != *b"\r\n" {
-		return Err(io::Error::new(ErrorKind::InvalidData, "Did not receive \\r\\n from LR command"));
-	}
+	let separator = data[data.len() - 1];
+	let data: Box<[u8]> = data[..data.len() - 1].into();
 	
-	// remove '\r\n'
-	let data = &data[..data.len() - 2];
+	// modules reporting signal information append it after the data
+	let signal = match separator {
+		b',' => Some(read_signal(buffer)?),
+		b'\r' if buffer.read_bytes(1)? == b"\n" => None,
+		_ => return Err(io::Error::new(ErrorKind::InvalidData, "Did not receive \\r\\n from LR command")),
+	};
+	
+	let length = data.len();
 	
 	message_sender.send(ATMessage {
 		address,
-		data: data.into(),
-	}).expect("mpsc receiver should not disconnect");
+		data,
+		signal,
+	}).map_err(|_| ErrorKind::ConnectionAborted)?;
 	
-	Ok(())
+	Ok(length)
+}
+
+fn read_signal(buffer: &mut DynReadBuffer<impl Read>) -> Result<ATSignal, io::Error> {
+	let signal = buffer.read_until(b'\n')?;
+	
+	let Some(signal) = signal.strip_suffix(b"\r\n") else {
+		return Err(io::Error::new(ErrorKind::InvalidData, "Did not receive \\r\\n from LR command"));
+	};
+	
+	let signal = str::from_utf8(signal)
+		.map_err(|_| ErrorKind::InvalidData)?;
+	
+	let Some((rssi, snr)) = signal.split_once(',') else {
+		return Err(io::Error::new(ErrorKind::InvalidData, "Invalid signal information"));
+	};
+	
+	Ok(ATSignal {
+		rssi: rssi.parse().map_err(|_| ErrorKind::InvalidData)?,
+		snr: snr.parse().map_err(|_| ErrorKind::InvalidData)?,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc;
+	
+	use super::*;
+	
+	fn read_message(data: &[u8]) -> Result<ATMessage, io::Error> {
+		let mut buffer = DynReadBuffer::new(data);
+		let (sender, receiver) = mpsc::channel();
+		
+		read_lr(&mut buffer, &sender)?;
+		
+		Ok(receiver.recv().unwrap())
+	}
+	
+	#[test]
+	fn message_without_signal() {
+		let message = read_message(b"1234,05,Hello\r\n").unwrap();
+		
+		assert_eq!(message.address, ATAddress::new(*b"1234").unwrap());
+		assert_eq!(&*message.data, b"Hello");
+		assert_eq!(message.signal, None);
+	}
+	
+	#[test]
+	fn message_with_signal() {
+		let message = read_message(b"1234,05,Hello,-87,-3\r\n").unwrap();
+		
+		assert_eq!(&*message.data, b"Hello");
+		assert_eq!(message.signal, Some(ATSignal {
+			rssi: -87,
+			snr: -3,
+		}));
+	}
+	
+	#[test]
+	fn data_containing_separator() {
+		let message = read_message(b"1234,03,a,b\r\n").unwrap();
+		
+		assert_eq!(&*message.data, b"a,b");
+		assert_eq!(message.signal, None);
+	}
+	
+	#[test]
+	fn invalid_signal() {
+		assert!(read_message(b"1234,05,Hello,loud\r\n").is_err());
+		assert!(read_message(b"1234,05,Hello;\r\n").is_err());
+	}
 }
\ No newline at end of file
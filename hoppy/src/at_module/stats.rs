@@ -0,0 +1,76 @@
+use std::{mem, sync::{Arc, Mutex, MutexGuard}, time::{Duration, Instant}};
+
+/// Counters of an `ATModule` since it was opened or the counters were last reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ATStats {
+	pub since: Instant,
+	pub frames_sent: u64,
+	pub bytes_sent: u64,
+	pub frames_received: u64,
+	pub bytes_received: u64,
+	/// Commands the module did not confirm and replies that could not be parsed
+	pub errors: u64,
+	/// Estimated time on air of the sent frames, see `ATConfig::time_on_air`
+	pub airtime: Duration,
+}
+
+impl ATStats {
+	fn new(since: Instant) -> Self {
+		Self {
+			since,
+			frames_sent: 0,
+			bytes_sent: 0,
+			frames_received: 0,
+			bytes_received: 0,
+			errors: 0,
+			airtime: Duration::ZERO,
+		}
+	}
+}
+
+/// Counters shared by an `ATModule` and its reader thread, readable from any thread
+#[derive(Clone)]
+pub struct ATCounters {
+	stats: Arc<Mutex<ATStats>>,
+}
+
+impl ATCounters {
+	pub(super) fn new() -> Self {
+		Self {
+			stats: Arc::new(Mutex::new(ATStats::new(Instant::now()))),
+		}
+	}
+	
+	fn stats_lock(&self) -> MutexGuard<'_, ATStats> {
+		self.stats.lock()
+			.expect("no threads should panic")
+	}
+	
+	pub fn snapshot(&self) -> ATStats {
+		*self.stats_lock()
+	}
+	
+	/// Starts counting from zero, returning the counters up to now
+	pub fn reset(&self) -> ATStats {
+		mem::replace(&mut *self.stats_lock(), ATStats::new(Instant::now()))
+	}
+	
+	pub(super) fn record_sent(&self, length: usize, airtime: Duration) {
+		let mut stats = self.stats_lock();
+		
+		stats.frames_sent += 1;
+		stats.bytes_sent += length as u64;
+		stats.airtime += airtime;
+	}
+	
+	pub(super) fn record_received(&self, length: usize) {
+		let mut stats = self.stats_lock();
+		
+		stats.frames_received += 1;
+		stats.bytes_received += length as u64;
+	}
+	
+	pub(super) fn record_error(&self) {
+		self.stats_lock().errors += 1;
+	}
+}
\ No newline at end of file
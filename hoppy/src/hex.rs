@@ -30,9 +30,9 @@ fn parse_ascii_hex_digit(ascii_digit: u8) -> Result<u8, io::Error> {
 	Ok(digit)
 }
 
-pub fn encode_ascii_hex<I: Integer<I>>(mut number: I) -> impl Iterator<Item = u8> // ideally would return `[u8; I::HEX_DIGITS]`
+pub fn encode_ascii_hex<I>(mut number: I) -> impl Iterator<Item = u8> // ideally would return `[u8; I::HEX_DIGITS]`
 where
-	I: BitAnd<Output = I>,
+	I: Integer<I> + BitAnd<Output = I>,
 	I::Error: Debug
 {
 	let mut ascii_data: Vec<u8> = Vec::with_capacity(I::HEX_DIGITS);
@@ -0,0 +1,5 @@
+pub mod aodv;
+pub mod at_module;
+
+mod hex;
+mod no_timeout_reader;
\ No newline at end of file
@@ -1,18 +1,44 @@
-use std::{time::Duration, thread, io};
-use aodv::AODVController;
-use at_module::{ATModule, at_address::ATAddress, ATConfig, HeaderMode, ReceiveMode};
+use std::{time::Duration, thread, io, env, collections::BTreeSet};
+use hoppy::aodv::{AODVController, AODVParameters, Etx, NodeInfo, Capabilities, ShutdownConfig, NeighborFilter};
+use hoppy::at_module::{ATModule, at_address::ATAddress, ATConfig, HeaderMode, ReceiveMode};
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 
-mod hex;
-mod no_timeout_reader;
-mod at_module;
-mod aodv;
+use metrics::MetricsServer;
+
+mod metrics;
 
 const BAUD_RATE: u32 = 9600;
-const HELLO_INTERVAL: Duration = Duration::from_secs(10);
-const HELLO_TIMEOUT: Duration = Duration::from_secs(25);
+/// Fraction of time on air allowed in the 433 MHz band
+const DUTY_CYCLE_LIMIT: f64 = 0.1;
+const SHUTDOWN_CONFIG: ShutdownConfig = ShutdownConfig {
+	flush_timeout: Some(Duration::from_secs(30)),
+	goodbye: true,
+};
 
 fn main() {
-	let mut args = std::env::args();
+	// RUST_LOG=debug also prints the routing table on every change
+	let filter = EnvFilter::try_from_default_env()
+		.unwrap_or_else(|_| EnvFilter::new("info"));
+	
+	tracing_subscriber::fmt()
+		.with_env_filter(filter)
+		.with_writer(io::stderr)
+		.init();
+	
+	// serves Prometheus metrics on localhost if set
+	let metrics_port = env::var("HOPPY_METRICS_PORT").ok()
+		.map(|port| port.parse::<u16>().expect("HOPPY_METRICS_PORT is not a valid port"));
+	
+	// comma-separated addresses, to emulate a topology when all radios hear each other
+	let neighbor_filter = match (env::var("HOPPY_ALLOW"), env::var("HOPPY_DENY")) {
+		(Ok(_), Ok(_)) => panic!("only one of HOPPY_ALLOW and HOPPY_DENY can be set"),
+		(Ok(allowed), _) => NeighborFilter::Allow(parse_addresses(&allowed)),
+		(_, Ok(denied)) => NeighborFilter::Deny(parse_addresses(&denied)),
+		_ => NeighborFilter::AllowAll,
+	};
+	
+	let mut args = env::args();
 	args.next(); // ignore first arg, which should be the executable's name
 	
 	let address = args.next()
@@ -26,6 +52,9 @@ fn main() {
 	let path = args.next()
 		.expect("no path provided");
 	
+	// optional name advertised to neighbors
+	let name = args.next();
+	
 	let port = serialport::new(path, BAUD_RATE)
 		.timeout(Duration::from_secs(10))
 		.open()
@@ -47,21 +76,50 @@ fn main() {
 		preamble_length: 8,
 	};
 	
+	let node_info = NodeInfo {
+		name,
+		capabilities: Capabilities {
+			relay: true,
+			..Default::default()
+		},
+		battery: None,
+	};
+	
+	let at_module_builder = ATModule::open(port, address, config)
+		.expect("failed to open at module");
+	
+	let controller = AODVController::start(at_module_builder, AODVParameters::lora(), Box::new(Etx), node_info, |address, data| {
+		let text = String::from_utf8_lossy(data);
+		println!("[DATA] {address}: {text}");
+	}).expect("could not start AODV controller");
+	
+	controller.set_neighbor_filter(neighbor_filter)
+		.expect("could not set neighbor filter");
+	
+	let metrics_server = metrics_port.map(|port| MetricsServer::bind(port)
+		.expect("could not bind metrics server"));
+	
 	thread::scope(|scope| {
-		let at_module_builder = ATModule::open(scope, port, address, config)
-			.expect("failed to open at module");
-		
-		let controller = AODVController::start(scope, at_module_builder, HELLO_INTERVAL, HELLO_TIMEOUT, |address, data| {
-			let text = String::from_utf8_lossy(data);
-			println!("[DATA] {address}: {text}");
-		});
+		if let Some(server) = &metrics_server {
+			let address = server.local_addr()
+				.expect("metrics server should have an address");
+			info!(%address, "Serving metrics");
+			
+			scope.spawn(|| server.serve(|| metrics::render(&controller, DUTY_CYCLE_LIMIT)));
+		}
 		
 		for line in io::stdin().lines() {
 			let line = line
 				.expect("couldn't read from stdin");
 			let line = line.as_bytes();
 			
-			let Ok(address): Result<[u8; 4], std::array::TryFromSliceError> = line[..4].try_into() else {
+			// lines starting with '!' are sent reliably
+			let (reliable, line) = match line.strip_prefix(b"!") {
+				Some(line) => (true, line),
+				None => (false, line),
+			};
+			
+			let Some(Ok(address)) = line.get(..4).map(<[u8; 4]>::try_from) else {
 				eprintln!("Invalid address!");
 				continue;
 			};
@@ -71,8 +129,39 @@ fn main() {
 				continue;
 			};
 			
-			controller.send(address, line[4..].into())
-				.expect("could not send data");
+			let result = if reliable {
+				controller.send_reliable(address, line[4..].into())
+			} else {
+				controller.send(address, line[4..].into())
+			};
+			
+			let delivery = result.expect("could not send data");
+			
+			thread::spawn(move || {
+				let status = delivery.wait();
+				println!("[DELIVERY] Message {:04X} to {address}: {status:?}", delivery.id());
+			});
+		}
+		
+		if let Some(server) = &metrics_server {
+			server.stop();
 		}
 	});
+	
+	// stdin was closed
+	controller.shutdown(SHUTDOWN_CONFIG);
+}
+
+fn parse_addresses(list: &str) -> BTreeSet<ATAddress> {
+	list.split(',')
+		.map(|address| {
+			let address = address.trim()
+				.as_bytes()
+				.try_into()
+				.expect("address in invalid format");
+			
+			ATAddress::new(address)
+				.expect("address in invalid format")
+		})
+		.collect()
 }
\ No newline at end of file
@@ -0,0 +1,365 @@
+use std::{fmt::{Display, Write as _}, io::{self, BufRead, BufReader, Write}, net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream}, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}};
+
+use tracing::warn;
+
+use hoppy::aodv::{AODVController, ControllerStats, LinkState, NeighborStats, QueueDepths};
+
+/// Requests are answered one at a time, so a client that stops sending or reading is given up on after this
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `/metrics` in the Prometheus text format on localhost
+pub struct MetricsServer {
+	listener: TcpListener,
+	stopped: AtomicBool,
+	request_timeout: Duration,
+}
+
+impl MetricsServer {
+	pub fn bind(port: u16) -> Result<Self, io::Error> {
+		let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+		
+		Ok(Self {
+			listener,
+			stopped: AtomicBool::new(false),
+			request_timeout: REQUEST_TIMEOUT,
+		})
+	}
+	
+	pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+		self.listener.local_addr()
+	}
+	
+	/// Answers requests one at a time until `stop` is called
+	pub fn serve(&self, render: impl Fn() -> String) {
+		for stream in self.listener.incoming() {
+			if self.stopped.load(Ordering::Relaxed) {
+				return;
+			}
+			
+			let result = stream.and_then(|stream| respond(stream, self.request_timeout, &render));
+			
+			if let Err(err) = result {
+				warn!(%err, "Could not answer metrics request");
+			}
+		}
+	}
+	
+	pub fn stop(&self) {
+		self.stopped.store(true, Ordering::Relaxed);
+		
+		// wakes up the accepting thread, which notices the flag
+		if let Ok(address) = self.local_addr() {
+			let _ = TcpStream::connect(address);
+		}
+	}
+}
+
+fn respond(mut stream: TcpStream, timeout: Duration, render: impl Fn() -> String) -> Result<(), io::Error> {
+	stream.set_read_timeout(Some(timeout))?;
+	stream.set_write_timeout(Some(timeout))?;
+	
+	let mut request_line = String::new();
+	BufReader::new(&stream).read_line(&mut request_line)?;
+	
+	let path = request_line.split_whitespace()
+		.nth(1);
+	
+	let (status, body) = match path {
+		Some("/metrics") => ("200 OK", render()),
+		_ => ("404 Not Found", String::new()),
+	};
+	
+	write!(stream, "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+}
+
+/// Text in the Prometheus exposition format
+struct Metrics {
+	output: String,
+}
+
+impl Metrics {
+	/// Starts a metric with its HELP and TYPE lines
+	fn header(&mut self, name: &str, kind: &str, help: &str) {
+		writeln!(self.output, "# HELP {name} {help}\n# TYPE {name} {kind}")
+			.expect("writing to a String does not fail");
+	}
+	
+	fn sample(&mut self, name: &str, label: Option<(&str, &dyn Display)>, value: impl Display) {
+		let result = match label {
+			Some((key, label)) => writeln!(self.output, "{name}{{{key}=\"{label}\"}} {value}"),
+			None => writeln!(self.output, "{name} {value}"),
+		};
+		
+		result.expect("writing to a String does not fail");
+	}
+}
+
+/// What the metrics are rendered from
+struct Snapshot {
+	/// Missing if the controller stopped
+	stats: Option<ControllerStats>,
+	route_count: usize,
+	queues: QueueDepths,
+	neighbors: Vec<NeighborStats>,
+}
+
+/// Renders the controller's counters and state, `duty_cycle_limit` being the allowed fraction of time on air
+pub fn render(controller: &AODVController, duty_cycle_limit: f64) -> String {
+	let snapshot = Snapshot {
+		stats: controller.stats().ok(),
+		route_count: controller.route_count(),
+		queues: controller.queue_depths(),
+		neighbors: controller.neighbor_stats(),
+	};
+	
+	render_snapshot(&snapshot, duty_cycle_limit, Instant::now())
+}
+
+fn render_snapshot(snapshot: &Snapshot, duty_cycle_limit: f64, now: Instant) -> String {
+	let mut metrics = Metrics {
+		output: String::new(),
+	};
+	
+	if let Some(stats) = &snapshot.stats {
+		let protocol = &stats.protocol;
+		
+		metrics.header("hoppy_frames_sent_total", "counter", "Frames sent by packet type, including retransmissions");
+		for (packet_type, count) in &protocol.frames_sent {
+			metrics.sample("hoppy_frames_sent_total", Some(("type", &format_args!("{:?}", packet_type))), count);
+		}
+		
+		metrics.header("hoppy_frames_received_total", "counter", "Frames received by packet type");
+		for (packet_type, count) in &protocol.frames_received {
+			metrics.sample("hoppy_frames_received_total", Some(("type", &format_args!("{:?}", packet_type))), count);
+		}
+		
+		metrics.header("hoppy_data_total", "counter", "Data packets originated, forwarded and delivered by this node");
+		metrics.sample("hoppy_data_total", Some(("direction", &"originated")), protocol.data_originated);
+		metrics.sample("hoppy_data_total", Some(("direction", &"forwarded")), protocol.data_forwarded);
+		metrics.sample("hoppy_data_total", Some(("direction", &"delivered")), protocol.data_delivered);
+		
+		metrics.header("hoppy_packets_dropped_total", "counter", "Received packets that were not handled, by reason");
+		for (reason, count) in &protocol.packets_dropped {
+			metrics.sample("hoppy_packets_dropped_total", Some(("reason", &format_args!("{:?}", reason))), count);
+		}
+		
+		metrics.header("hoppy_deliveries_failed_total", "counter", "Data sent from this node that failed, by reason");
+		for (failure, count) in &protocol.deliveries_failed {
+			metrics.sample("hoppy_deliveries_failed_total", Some(("reason", &format_args!("{:?}", failure))), count);
+		}
+		
+		metrics.header("hoppy_discoveries_total", "counter", "Route discoveries by outcome");
+		metrics.sample("hoppy_discoveries_total", Some(("outcome", &"started")), protocol.discoveries_started);
+		metrics.sample("hoppy_discoveries_total", Some(("outcome", &"succeeded")), protocol.discoveries_succeeded);
+		metrics.sample("hoppy_discoveries_total", Some(("outcome", &"failed")), protocol.discoveries_failed);
+		
+		metrics.header("hoppy_discovery_latency_seconds_total", "counter", "Summed up duration of successful route discoveries");
+		metrics.sample("hoppy_discovery_latency_seconds_total", None, protocol.discovery_latency.as_secs_f64());
+		
+		metrics.header("hoppy_retries_total", "counter", "Retransmissions by layer");
+		metrics.sample("hoppy_retries_total", Some(("layer", &"link")), protocol.link_retries);
+		metrics.sample("hoppy_retries_total", Some(("layer", &"delivery")), protocol.delivery_retries);
+		
+		let at_module = &stats.at_module;
+		
+		metrics.header("hoppy_at_errors_total", "counter", "AT commands that failed and replies that could not be parsed");
+		metrics.sample("hoppy_at_errors_total", None, at_module.errors);
+		
+		metrics.header("hoppy_at_bytes_total", "counter", "Bytes passed through the AT module");
+		metrics.sample("hoppy_at_bytes_total", Some(("direction", &"sent")), at_module.bytes_sent);
+		metrics.sample("hoppy_at_bytes_total", Some(("direction", &"received")), at_module.bytes_received);
+		
+		metrics.header("hoppy_airtime_seconds_total", "counter", "Estimated time on air of sent frames");
+		metrics.sample("hoppy_airtime_seconds_total", None, at_module.airtime.as_secs_f64());
+		
+		let elapsed = now.duration_since(at_module.since).as_secs_f64();
+		let duty_cycle = if elapsed > 0.0 { at_module.airtime.as_secs_f64() / elapsed } else { 0.0 };
+		
+		metrics.header("hoppy_duty_cycle_ratio", "gauge", "Fraction of time on air since the counters were reset");
+		metrics.sample("hoppy_duty_cycle_ratio", None, duty_cycle);
+	}
+	
+	metrics.header("hoppy_duty_cycle_limit_ratio", "gauge", "Fraction of time on air the band allows");
+	metrics.sample("hoppy_duty_cycle_limit_ratio", None, duty_cycle_limit);
+	
+	metrics.header("hoppy_routes", "gauge", "Stored routes, not counting neighbors");
+	metrics.sample("hoppy_routes", None, snapshot.route_count);
+	
+	let queues = snapshot.queues;
+	
+	metrics.header("hoppy_queue_depth", "gauge", "Entries waiting in the protocol's queues");
+	metrics.sample("hoppy_queue_depth", Some(("queue", &"outbound_messages")), queues.outbound_messages);
+	metrics.sample("hoppy_queue_depth", Some(("queue", &"pending_deliveries")), queues.pending_deliveries);
+	metrics.sample("hoppy_queue_depth", Some(("queue", &"pending_link_acks")), queues.pending_link_acks);
+	metrics.sample("hoppy_queue_depth", Some(("queue", &"pending_broadcasts")), queues.pending_broadcasts);
+	
+	let neighbors = &snapshot.neighbors;
+	
+	metrics.header("hoppy_neighbor_up", "gauge", "Whether the link to a neighbor is up");
+	for neighbor in neighbors {
+		metrics.sample("hoppy_neighbor_up", Some(("neighbor", &neighbor.address)), u8::from(neighbor.state == LinkState::Up));
+	}
+	
+	metrics.header("hoppy_neighbor_hello_ratio", "gauge", "Fraction of a neighbor's recent hellos that were received");
+	for neighbor in neighbors {
+		metrics.sample("hoppy_neighbor_hello_ratio", Some(("neighbor", &neighbor.address)), neighbor.link_quality.hello_ratio);
+	}
+	
+	metrics.header("hoppy_neighbor_rssi_dbm", "gauge", "Smoothed average signal strength of the frames from a neighbor");
+	for neighbor in neighbors {
+		if let Some(rssi) = neighbor.link_quality.rssi {
+			metrics.sample("hoppy_neighbor_rssi_dbm", Some(("neighbor", &neighbor.address)), rssi);
+		}
+	}
+	
+	metrics.header("hoppy_neighbor_snr_db", "gauge", "Smoothed average signal-to-noise ratio of the frames from a neighbor");
+	for neighbor in neighbors {
+		if let Some(snr) = neighbor.link_quality.snr {
+			metrics.sample("hoppy_neighbor_snr_db", Some(("neighbor", &neighbor.address)), snr);
+		}
+	}
+	
+	metrics.output
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::BTreeMap, io::Read, thread};
+	
+	use hoppy::{aodv::{AODVStats, LinkQuality, NodeInfo, PacketType}, at_module::{ATStats, at_address::ATAddress}};
+	
+	use super::*;
+	
+	fn snapshot(now: Instant) -> Snapshot {
+		let since = now - Duration::from_secs(100);
+		
+		let protocol = AODVStats {
+			since,
+			frames_sent: BTreeMap::from([(PacketType::Hello, 12), (PacketType::RouteRequest, 3)]),
+			frames_received: BTreeMap::new(),
+			data_originated: 4,
+			data_forwarded: 2,
+			data_delivered: 1,
+			packets_dropped: BTreeMap::new(),
+			deliveries_failed: BTreeMap::new(),
+			discoveries_started: 3,
+			discoveries_succeeded: 2,
+			discoveries_failed: 1,
+			discovery_latency: Duration::from_millis(1500),
+			link_retries: 5,
+			delivery_retries: 0,
+		};
+		
+		let at_module = ATStats {
+			since,
+			frames_sent: 15,
+			bytes_sent: 600,
+			frames_received: 20,
+			bytes_received: 800,
+			errors: 1,
+			airtime: Duration::from_secs(2),
+		};
+		
+		Snapshot {
+			stats: Some(ControllerStats {
+				protocol,
+				at_module,
+			}),
+			route_count: 2,
+			queues: QueueDepths {
+				outbound_messages: 1,
+				..QueueDepths::default()
+			},
+			neighbors: vec![NeighborStats {
+				address: ATAddress::new(*b"0002").unwrap(),
+				state: LinkState::Up,
+				link_quality: LinkQuality {
+					hello_ratio: 0.5,
+					rssi: Some(-90),
+					snr: None,
+				},
+				last_heard: now,
+				info: NodeInfo::default(),
+				neighbors: Vec::new(),
+			}],
+		}
+	}
+	
+	#[test]
+	fn renders_exposition_format() {
+		let now = Instant::now();
+		let output = render_snapshot(&snapshot(now), 0.1, now);
+		
+		assert!(output.contains("# HELP hoppy_frames_sent_total Frames sent by packet type, including retransmissions\n# TYPE hoppy_frames_sent_total counter\n"));
+		assert!(output.contains("hoppy_frames_sent_total{type=\"Hello\"} 12\n"));
+		assert!(output.contains("hoppy_frames_sent_total{type=\"RouteRequest\"} 3\n"));
+		assert!(output.contains("hoppy_data_total{direction=\"forwarded\"} 2\n"));
+		assert!(output.contains("hoppy_discovery_latency_seconds_total 1.5\n"));
+		assert!(output.contains("hoppy_retries_total{layer=\"link\"} 5\n"));
+		assert!(output.contains("hoppy_at_bytes_total{direction=\"received\"} 800\n"));
+		assert!(output.contains("hoppy_duty_cycle_ratio 0.02\n"));
+		assert!(output.contains("hoppy_duty_cycle_limit_ratio 0.1\n"));
+		assert!(output.contains("hoppy_routes 2\n"));
+		assert!(output.contains("hoppy_queue_depth{queue=\"outbound_messages\"} 1\n"));
+		assert!(output.contains("# TYPE hoppy_neighbor_up gauge\nhoppy_neighbor_up{neighbor=\"0002\"} 1\n"));
+		assert!(output.contains("hoppy_neighbor_rssi_dbm{neighbor=\"0002\"} -90\n"));
+		
+		// neighbors without a value get no sample
+		assert!(output.contains("# TYPE hoppy_neighbor_snr_db gauge\n"));
+		assert!(!output.contains("hoppy_neighbor_snr_db{"));
+		
+		// every sample belongs to a declared metric
+		for line in output.lines().filter(|line| !line.starts_with('#')) {
+			let name = line.split(['{', ' ']).next().unwrap();
+			assert!(output.contains(&format!("# TYPE {name} ")), "{line}");
+		}
+	}
+	
+	#[test]
+	fn skips_counters_of_stopped_controller() {
+		let now = Instant::now();
+		let snapshot = Snapshot {
+			stats: None,
+			..snapshot(now)
+		};
+		
+		let output = render_snapshot(&snapshot, 0.1, now);
+		
+		assert!(!output.contains("hoppy_frames_sent_total"));
+		assert!(output.contains("hoppy_routes 2\n"));
+	}
+	
+	fn request(address: SocketAddr, path: &str) -> String {
+		let mut stream = TcpStream::connect(address).unwrap();
+		write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+		
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}
+	
+	#[test]
+	fn serves_metrics() {
+		let mut server = MetricsServer::bind(0).unwrap();
+		server.request_timeout = Duration::from_millis(100);
+		
+		let address = server.local_addr().unwrap();
+		
+		thread::scope(|scope| {
+			scope.spawn(|| server.serve(|| "hoppy_routes 2\n".to_owned()));
+			
+			// a client that never sends its request doesn't block the others
+			let _idle = TcpStream::connect(address).unwrap();
+			
+			let response = request(address, "/metrics");
+			assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+			assert!(response.contains("Content-Length: 15\r\n"));
+			assert!(response.ends_with("\r\n\r\nhoppy_routes 2\n"));
+			
+			let response = request(address, "/");
+			assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+			
+			server.stop();
+		});
+	}
+}
\ No newline at end of file
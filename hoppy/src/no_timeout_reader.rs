@@ -1,13 +1,16 @@
-use std::io::{Read, self, ErrorKind};
+use std::{io::{Read, self, ErrorKind}, sync::{Arc, atomic::{AtomicBool, Ordering}}};
 
+/// Retries reads that timed out, until `stopped` is set
 pub struct NoTimeoutReader<R: Read> {
 	reader: R,
+	stopped: Arc<AtomicBool>,
 }
 
 impl<R: Read> NoTimeoutReader<R> {
-	pub fn new(reader: R) -> Self {
+	pub fn new(reader: R, stopped: Arc<AtomicBool>) -> Self {
 		Self {
 			reader,
+			stopped,
 		}
 	}
 }
@@ -16,7 +19,11 @@ impl<R: Read> Read for NoTimeoutReader<R> {
 	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
 		loop {
 			match self.reader.read(buf) {
-				Err(err) if err.kind() == ErrorKind::TimedOut => continue,
+				Err(err) if err.kind() == ErrorKind::TimedOut => {
+					if self.stopped.load(Ordering::Relaxed) {
+						return Err(ErrorKind::ConnectionAborted.into());
+					}
+				},
 				result => return result,
 			}
 		}